@@ -0,0 +1,123 @@
+use flate2::read::GzDecoder;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+// ROM_EXTENSIONS are the files looked for in a zip archive.
+const ROM_EXTENSIONS: [&str; 2] = ["gb", "gbc"];
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    return path.extension().map_or(false, |e| e.eq_ignore_ascii_case(extension));
+}
+
+fn is_rom(name: &str) -> bool {
+    return ROM_EXTENSIONS.iter().any(|extension| has_extension(Path::new(name), extension));
+}
+
+// is_archive returns whether a file is a zip or gzip archive, going by its
+// extension.
+pub fn is_archive(path: &Path) -> bool {
+    return has_extension(path, "zip") || has_extension(path, "gz");
+}
+
+fn invalid_data<E: ToString>(e: E) -> io::Error {
+    return io::Error::new(io::ErrorKind::InvalidData, e.to_string());
+}
+
+// rom_entries returns the names of the ROMs in an archive. A gzip archive
+// only ever holds the one file, named like the archive without .gz.
+pub fn rom_entries(path: &Path) -> io::Result<Vec<String>> {
+    if has_extension(path, "gz") {
+        let name = path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().to_string());
+        return Ok(vec![name]);
+    }
+    let mut zip = ZipArchive::new(File::open(path)?).map_err(invalid_data)?;
+    let mut entries = Vec::new();
+    for i in 0..zip.len() {
+        let name = zip.by_index(i).map_err(invalid_data)?.name().to_string();
+        if is_rom(&name) {
+            entries.push(name);
+        }
+    }
+    return Ok(entries);
+}
+
+// read_archive returns the name and contents of a ROM in an archive, the
+// given entry or else the first one.
+pub fn read_archive(path: &Path, entry: Option<&str>) -> io::Result<(String, Vec<u8>)> {
+    let name = match entry {
+        Some(entry) => entry.to_string(),
+        None => rom_entries(path)?.into_iter().next().ok_or_else(|| {
+            invalid_data(format!("no .gb or .gbc file in {}", path.display()))
+        })?,
+    };
+
+    let mut rom = Vec::new();
+    if has_extension(path, "gz") {
+        GzDecoder::new(File::open(path)?).read_to_end(&mut rom)?;
+    } else {
+        let mut zip = ZipArchive::new(File::open(path)?).map_err(invalid_data)?;
+        zip.by_name(&name).map_err(invalid_data)?.read_to_end(&mut rom)?;
+    }
+    return Ok((name, rom));
+}
+
+// rom_path returns where a ROM from an archive would be if it were next to
+// the archive, which is where its save and patch are kept.
+pub fn rom_path(archive: &Path, name: &str) -> PathBuf {
+    let file_name = Path::new(name).file_name().unwrap_or_default();
+    return archive.with_file_name(file_name);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cart::archive::{read_archive, rom_entries, rom_path};
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use std::path::Path;
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    #[test]
+    fn reads_roms_from_zip_archives() {
+        let path = std::env::temp_dir().join(format!("gameboy-archive-{}.zip", std::process::id()));
+        let mut zip = ZipWriter::new(std::fs::File::create(&path).unwrap());
+        for (name, data) in [("readme.txt", b"hi"), ("roms/first.GB", b"ab"), ("second.gbc", b"cd")].iter() {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(*data).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let entries = rom_entries(&path).unwrap();
+        let first = read_archive(&path, None).unwrap();
+        let second = read_archive(&path, Some("second.gbc")).unwrap();
+        let missing = read_archive(&path, Some("third.gb"));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(entries, vec!["roms/first.GB", "second.gbc"]);
+        assert_eq!(first, ("roms/first.GB".to_string(), b"ab".to_vec()));
+        assert_eq!(second, ("second.gbc".to_string(), b"cd".to_vec()));
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn reads_roms_from_gzip_archives() {
+        let name = format!("gameboy-archive-{}.gb", std::process::id());
+        let path = std::env::temp_dir().join(format!("{}.gz", name));
+        let mut gz = GzEncoder::new(std::fs::File::create(&path).unwrap(), Compression::default());
+        gz.write_all(b"rom").unwrap();
+        gz.finish().unwrap();
+
+        let rom = read_archive(&path, None);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(rom.unwrap(), (name, b"rom".to_vec()));
+    }
+
+    #[test]
+    fn roms_are_kept_next_to_archives() {
+        let path = rom_path(Path::new("/games/set.zip"), "roms/first.gb");
+        assert_eq!(path, Path::new("/games/first.gb"));
+    }
+}
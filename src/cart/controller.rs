@@ -3,24 +3,42 @@ use crate::cart::mbc2::MBC2;
 use crate::cart::mbc3::MBC3;
 use crate::cart::mbc5::MBC5;
 use crate::cart::rom::ROM;
+use crate::cart::archive::{is_archive, read_archive, rom_path};
+use crate::cart::patch::{apply_patch, find_patch};
 use crate::cart::BankingController;
+use crate::cheats::GameGenie;
 use std::fs::File;
 use std::io::prelude::*;
+use std::path::Path;
 
 const GB_MODE: u8 = 1;
 pub const CBG_MODE: u8 = 2;
 const BOTH_MODE: u8 = 3;
+// HEADER_END is where the cartridge header ends.
+const HEADER_END: usize = 0x150;
 
 pub struct Cart {
     banking_controller: Box<dyn BankingController>,
-    pub(crate) title: String,
-    filename: String,
+    pub title: String,
+    // Where the ROM is, or would be if it came from an archive. Its save is
+    // kept next to it.
+    pub filename: String,
     pub mode: u8,
+    // The Game Genie codes patching ROM reads.
+    pub game_genie: Vec<GameGenie>,
 }
 
 impl Cart {
     pub fn read(&self, address: u16) -> u8 {
-        return self.banking_controller.read(address);
+        let value = self.banking_controller.read(address);
+        if address < 0x8000 {
+            for code in self.game_genie.iter() {
+                if code.address == address && code.compare.map_or(true, |compare| compare == value) {
+                    return code.value;
+                }
+            }
+        }
+        return value;
     }
 
     pub fn write_rom(&mut self, address: u16, value: u8) {
@@ -72,8 +90,51 @@ impl Cart {
         }
     }
 
-    pub fn new(filename: &str) -> Cart {
-        let rom = Cart::read_rom_data(String::from(filename));
+    // new loads a ROM, patched by any patch next to it with the same name.
+    pub fn new(filename: &str) -> Result<Cart, String> {
+        return Cart::open(filename, None, None);
+    }
+
+    // open loads a ROM, or the given entry of a zip archive, patched by the
+    // given IPS, UPS or BPS patch or else the one found next to it. ROMs from
+    // archives are saved and patched as if they were next to the archive.
+    // It fails if the ROM can't be read, but not if the patch can't be.
+    pub fn open(filename: &str, entry: Option<&str>, patch: Option<&Path>) -> Result<Cart, String> {
+        let path = Path::new(filename);
+        let (mut rom, filename) = if is_archive(path) {
+            let (name, rom) = read_archive(path, entry)
+                .map_err(|e| format!("Unable to read ROM from {}: {}", path.display(), e))?;
+            (rom, rom_path(path, &name).to_string_lossy().to_string())
+        } else {
+            let rom = std::fs::read(path).map_err(|e| format!("Unable to read ROM {}: {}", filename, e))?;
+            (rom, String::from(filename))
+        };
+        let patch = match patch {
+            Some(patch) => Some(patch.to_path_buf()),
+            None => find_patch(Path::new(&filename)),
+        };
+        if let Some(patch) = patch {
+            let result = std::fs::read(&patch)
+                .map_err(|e| e.to_string())
+                .and_then(|data| apply_patch(&rom, &data));
+            match result {
+                Ok(patched) => {
+                    println!("Applied patch {}", patch.display());
+                    rom = patched;
+                }
+                Err(e) => println!("Unable to apply patch {}: {}", patch.display(), e),
+            }
+        }
+        return Cart::from_rom(rom, &filename);
+    }
+
+    // from_rom creates a cart from ROM data that is already loaded. Save data
+    // is still kept next to filename. The ROM has to be big enough to hold
+    // the header.
+    pub fn from_rom(rom: Vec<u8>, filename: &str) -> Result<Cart, String> {
+        if rom.len() < HEADER_END {
+            return Err(format!("ROM is {} bytes, too small to hold a header", rom.len()));
+        }
         let mode: u8;
         match rom[0x0143] {
             0x80 => mode = BOTH_MODE,
@@ -99,6 +160,7 @@ impl Cart {
             title: String::from(title.trim()),
             filename: String::from(filename),
             mode,
+            game_genie: Vec::new(),
         };
         match flag {
             0x3 | 0x6 | 0x9 | 0xD | 0xF | 0x10 | 0x13 | 0x17 | 0x1B | 0x1E | 0xFF => {
@@ -107,17 +169,42 @@ impl Cart {
             _ => {}
         }
 
-        return cart;
+        return Ok(cart);
     }
+}
 
-    pub fn read_rom_data(filename: String) -> Vec<u8> {
-        let file = File::open(filename);
-        if file.is_err() {
-            // TODO
-            return Vec::new();
-        }
-        let mut contents: Vec<u8> = Vec::new();
-        let result = file.unwrap().read_to_end(&mut contents).expect("Unable to open ROM");
-        return contents;
+#[cfg(test)]
+mod tests {
+    use crate::cart::controller::Cart;
+    use std::io::Write;
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    #[test]
+    fn rejects_roms_too_small_to_hold_a_header() {
+        assert!(Cart::from_rom(Vec::new(), "").is_err());
+        assert!(Cart::from_rom(vec![0; 0x14F], "").is_err());
+        assert!(Cart::from_rom(vec![0; 0x150], "").is_ok());
+    }
+
+    #[test]
+    fn fails_to_open_unreadable_roms() {
+        let dir = std::env::temp_dir();
+        let missing = dir.join(format!("gameboy-missing-{}.gb", std::process::id()));
+        assert!(Cart::open(missing.to_str().unwrap(), None, None).is_err());
+
+        let path = dir.join(format!("gameboy-cart-{}.zip", std::process::id()));
+        let mut zip = ZipWriter::new(std::fs::File::create(&path).unwrap());
+        zip.start_file("readme.txt", FileOptions::default()).unwrap();
+        zip.write_all(b"hi").unwrap();
+        zip.start_file("short.gb", FileOptions::default()).unwrap();
+        zip.write_all(b"ab").unwrap();
+        zip.finish().unwrap();
+
+        let no_entry = Cart::open(path.to_str().unwrap(), Some("other.gb"), None);
+        let short = Cart::open(path.to_str().unwrap(), None, None);
+        std::fs::remove_file(&path).unwrap();
+        assert!(no_entry.is_err());
+        assert!(short.is_err());
     }
 }
@@ -1,8 +1,10 @@
-pub(crate) mod controller;
+pub mod archive;
+pub mod controller;
 mod mbc1;
 mod mbc2;
 mod mbc3;
 mod mbc5;
+pub mod patch;
 mod rom;
 
 // BankingController provides methods for accessing and writing data to a
@@ -0,0 +1,305 @@
+use std::path::{Path, PathBuf};
+
+// PATCH_EXTENSIONS are the patch formats looked for next to a ROM, in order.
+const PATCH_EXTENSIONS: [&str; 3] = ["ips", "ups", "bps"];
+
+// IPS_EOF is the offset that marks the end of an IPS patch's records.
+const IPS_EOF: usize = 0x454F46;
+
+// find_patch returns the patch next to a ROM with the same name, like
+// game.ips for game.gb, if there is one.
+pub fn find_patch(rom: &Path) -> Option<PathBuf> {
+    return PATCH_EXTENSIONS.iter()
+        .map(|extension| rom.with_extension(extension))
+        .find(|path| path.is_file());
+}
+
+// apply_patch returns the ROM with an IPS, UPS or BPS patch applied, telling
+// them apart by their header. UPS and BPS patches carry checksums of the ROM
+// they expect and the ROM they make, and are refused if either is wrong.
+pub fn apply_patch(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.starts_with(b"PATCH") {
+        return apply_ips(rom, patch);
+    }
+    if patch.starts_with(b"UPS1") {
+        return apply_ups(rom, patch);
+    }
+    if patch.starts_with(b"BPS1") {
+        return apply_bps(rom, patch);
+    }
+    return Err("not an IPS, UPS or BPS patch".to_string());
+}
+
+// Reader reads the numbers patches are made of, failing at the end of the
+// patch rather than panicking.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, String> {
+        let byte = *self.data.get(self.pos).ok_or("patch ended early")?;
+        self.pos += 1;
+        return Ok(byte);
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.data.len() - self.pos < len {
+            return Err("patch ended early".to_string());
+        }
+        self.pos += len;
+        return Ok(&self.data[self.pos - len..self.pos]);
+    }
+
+    // big_endian reads an IPS number of len bytes.
+    fn big_endian(&mut self, len: usize) -> Result<usize, String> {
+        return Ok(self.bytes(len)?.iter().fold(0, |n, &byte| n << 8 | byte as usize));
+    }
+
+    // varint reads a UPS or BPS number. Each byte holds 7 bits, the last one
+    // has the top bit set, and each extra byte also adds one so that every
+    // number has only one encoding.
+    fn varint(&mut self) -> Result<usize, String> {
+        let mut value: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let byte = self.byte()?;
+            value = value.checked_add((byte & 0x7F) as usize * shift).ok_or("patch number is too large")?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_mul(0x80).ok_or("patch number is too large")?;
+            value = value.checked_add(shift).ok_or("patch number is too large")?;
+        }
+    }
+}
+
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    let mut target = rom.to_vec();
+    let mut reader = Reader { data: patch, pos: 5 };
+    loop {
+        let offset = reader.big_endian(3)?;
+        if offset == IPS_EOF {
+            break;
+        }
+        let len = reader.big_endian(2)?;
+        // A length of 0 is a run of one byte repeated.
+        let data = if len == 0 {
+            let len = reader.big_endian(2)?;
+            vec![reader.byte()?; len]
+        } else {
+            reader.bytes(len)?.to_vec()
+        };
+        if target.len() < offset + data.len() {
+            target.resize(offset + data.len(), 0);
+        }
+        target[offset..offset + data.len()].copy_from_slice(&data);
+    }
+    // Some patches end with the size to cut the ROM down to.
+    if let Ok(len) = reader.big_endian(3) {
+        target.truncate(len);
+    }
+    return Ok(target);
+}
+
+// checksums reads the CRC32s at the end of a UPS or BPS patch, of the source
+// ROM, the target ROM and the patch itself, and checks the one of the patch
+// and source.
+fn checksums(rom: &[u8], patch: &[u8]) -> Result<(u32, u32), String> {
+    if patch.len() < 16 {
+        return Err("patch ended early".to_string());
+    }
+    let footer = patch.len() - 12;
+    let crc = |i: usize| u32::from_le_bytes([patch[i], patch[i + 1], patch[i + 2], patch[i + 3]]);
+    let (source, target, patch_crc) = (crc(footer), crc(footer + 4), crc(footer + 8));
+    if crc32fast::hash(&patch[..footer + 8]) != patch_crc {
+        return Err("patch is corrupted, its checksum doesn't match".to_string());
+    }
+    let rom_crc = crc32fast::hash(rom);
+    if rom_crc != source {
+        return Err(format!(
+            "patch is for a ROM with checksum {:08X}, but this ROM's is {:08X}", source, rom_crc
+        ));
+    }
+    return Ok((source, target));
+}
+
+fn check_target(target: &[u8], expected: u32) -> Result<(), String> {
+    let crc = crc32fast::hash(target);
+    if crc != expected {
+        return Err(format!("patched ROM should have checksum {:08X}, but has {:08X}", expected, crc));
+    }
+    return Ok(());
+}
+
+fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    let (_, target_crc) = checksums(rom, patch)?;
+    let end = patch.len() - 12;
+    let mut reader = Reader { data: &patch[..end], pos: 4 };
+    let source_len = reader.varint()?;
+    let target_len = reader.varint()?;
+    if source_len != rom.len() {
+        return Err(format!("patch is for a ROM of {} bytes, but this ROM is {} bytes", source_len, rom.len()));
+    }
+
+    // Each record skips some bytes, then XORs bytes with the source up to and
+    // including a 0.
+    let mut target = rom.to_vec();
+    target.resize(target_len, 0);
+    let mut offset: usize = 0;
+    while reader.pos < end {
+        offset = offset.checked_add(reader.varint()?).ok_or("patch number is too large")?;
+        loop {
+            let byte = reader.byte()?;
+            if offset < target_len {
+                target[offset] ^= byte;
+            }
+            offset += 1;
+            if byte == 0 {
+                break;
+            }
+        }
+    }
+    check_target(&target, target_crc)?;
+    return Ok(target);
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    let (_, target_crc) = checksums(rom, patch)?;
+    let end = patch.len() - 12;
+    let mut reader = Reader { data: &patch[..end], pos: 4 };
+    let source_len = reader.varint()?;
+    let target_len = reader.varint()?;
+    let metadata_len = reader.varint()?;
+    reader.bytes(metadata_len)?;
+    if source_len != rom.len() {
+        return Err(format!("patch is for a ROM of {} bytes, but this ROM is {} bytes", source_len, rom.len()));
+    }
+
+    let mut target: Vec<u8> = Vec::with_capacity(target_len);
+    let mut source_offset: isize = 0;
+    let mut target_offset: isize = 0;
+    let out_of_range = || "patch copies from outside the ROM".to_string();
+    while reader.pos < end {
+        let action = reader.varint()?;
+        let len = (action >> 2) + 1;
+        if target_len - target.len() < len {
+            return Err("patch writes past the end of the patched ROM".to_string());
+        }
+        match action & 3 {
+            // SourceRead copies from the same place in the source.
+            0 => {
+                let start = target.len();
+                target.extend_from_slice(rom.get(start..start + len).ok_or_else(out_of_range)?);
+            }
+            // TargetRead copies from the patch.
+            1 => target.extend_from_slice(reader.bytes(len)?),
+            // SourceCopy and TargetCopy copy from anywhere in the source or
+            // what has been written of the target, relative to where the last
+            // copy of the same kind ended.
+            command => {
+                let relative = reader.varint()?;
+                let step = (relative >> 1) as isize * if relative & 1 != 0 { -1 } else { 1 };
+                if command == 2 {
+                    source_offset += step;
+                    let start = source_offset as usize;
+                    if source_offset < 0 || start + len > rom.len() {
+                        return Err(out_of_range());
+                    }
+                    target.extend_from_slice(&rom[start..start + len]);
+                    source_offset += len as isize;
+                } else {
+                    target_offset += step;
+                    if target_offset < 0 || target_offset as usize >= target.len() {
+                        return Err(out_of_range());
+                    }
+                    // The copy can overlap what it writes, repeating bytes.
+                    for _ in 0..len {
+                        target.push(target[target_offset as usize]);
+                        target_offset += 1;
+                    }
+                }
+            }
+        }
+    }
+    if target.len() != target_len {
+        return Err(format!("patched ROM should be {} bytes, but is {} bytes", target_len, target.len()));
+    }
+    check_target(&target, target_crc)?;
+    return Ok(target);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cart::patch::apply_patch;
+
+    // with_checksums finishes a UPS or BPS patch with the CRC32s of the source,
+    // target and patch.
+    fn with_checksums(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+        patch.extend_from_slice(&crc32fast::hash(target).to_le_bytes());
+        let crc = crc32fast::hash(&patch);
+        patch.extend_from_slice(&crc.to_le_bytes());
+        return patch;
+    }
+
+    #[test]
+    fn applies_ips_patches() {
+        let rom = vec![0; 8];
+        let mut patch = b"PATCH".to_vec();
+        // 2 bytes at 1, then a run of 3 0xAA at 6, past the end of the ROM.
+        patch.extend_from_slice(&[0, 0, 1, 0, 2, 0x11, 0x22]);
+        patch.extend_from_slice(&[0, 0, 6, 0, 0, 0, 3, 0xAA]);
+        patch.extend_from_slice(b"EOF");
+        assert_eq!(apply_patch(&rom, &patch), Ok(vec![0, 0x11, 0x22, 0, 0, 0, 0xAA, 0xAA, 0xAA]));
+
+        // Truncated to 4 bytes.
+        patch.extend_from_slice(&[0, 0, 4]);
+        assert_eq!(apply_patch(&rom, &patch), Ok(vec![0, 0x11, 0x22, 0]));
+    }
+
+    #[test]
+    fn applies_ups_patches() {
+        let rom = vec![1, 2, 3, 4];
+        let target = vec![1, 7, 3, 4, 9];
+        // Sizes 4 and 5, then skip 1 and XOR 2^7 and a 0, then skip 1 and XOR
+        // 0^9 and a 0.
+        let patch = b"UPS1".iter().cloned().chain(vec![0x84, 0x85, 0x81, 2 ^ 7, 0, 0x81, 9, 0]).collect();
+        let patch = with_checksums(patch, &rom, &target);
+        assert_eq!(apply_patch(&rom, &patch), Ok(target));
+        assert!(apply_patch(&[1, 2, 3, 5], &patch).unwrap_err().contains("checksum"));
+    }
+
+    #[test]
+    fn applies_bps_patches() {
+        let rom = vec![1, 2, 3, 4];
+        let target = vec![1, 2, 9, 9, 9, 3, 4];
+        let patch: Vec<u8> = b"BPS1".iter().cloned().chain(vec![
+            // Sizes 4 and 7, no metadata.
+            0x84, 0x87, 0x80,
+            // SourceRead 2, TargetRead 1 of 9, TargetCopy 2 from offset 2.
+            0x84, 0x81, 9, 0x87, 0x84,
+            // SourceCopy 2 from offset 2.
+            0x86, 0x84,
+        ]).collect();
+        let good = with_checksums(patch.clone(), &rom, &target);
+        assert_eq!(apply_patch(&rom, &good), Ok(target));
+
+        let bad = with_checksums(patch, &rom, &[0]);
+        assert!(apply_patch(&rom, &bad).unwrap_err().contains("should have checksum"));
+    }
+
+    #[test]
+    fn rejects_corrupted_patches() {
+        let rom = vec![1, 2, 3, 4];
+        // A SourceRead of the whole ROM.
+        let patch = b"BPS1".iter().cloned().chain(vec![0x84, 0x84, 0x80, 0x8C]).collect();
+        let mut patch = with_checksums(patch, &rom, &rom);
+        assert_eq!(apply_patch(&rom, &patch), Ok(rom.clone()));
+        patch[7] = 0x88;
+        assert!(apply_patch(&rom, &patch).unwrap_err().contains("corrupted"));
+        assert!(apply_patch(&rom, b"PATCH\x00\x00").is_err());
+        assert!(apply_patch(&rom, b"nothing").is_err());
+    }
+}
@@ -0,0 +1,282 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use crate::gameboy::Gameboy;
+use crate::memory::{wram_bank_index, MemoryAddr};
+
+// GameGenie replaces a byte read from ROM. With a compare value it only does
+// so while the ROM holds that byte, which is how a code picks out one bank of
+// the switchable ROM area.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GameGenie {
+    pub address: MemoryAddr,
+    pub value: u8,
+    pub compare: Option<u8>,
+}
+
+// GameShark writes a byte to RAM every frame. The type byte is 0x01 for
+// normal codes, or 0x9X on the CGB to write to WRAM bank X.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GameShark {
+    pub bank: u8,
+    pub value: u8,
+    pub address: MemoryAddr,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Code {
+    GameGenie(GameGenie),
+    GameShark(GameShark),
+}
+
+pub struct Cheat {
+    pub code: Code,
+    // The code as it was written.
+    pub text: String,
+    pub name: String,
+    pub enabled: bool,
+}
+
+fn hex_digits(text: &str) -> Result<Vec<u8>, String> {
+    return text.chars().filter(|&c| c != '-').map(|c| {
+        c.to_digit(16).map(|digit| digit as u8).ok_or(format!("{} is not a hex digit", c))
+    }).collect();
+}
+
+impl Code {
+    // parse reads a Game Genie code, ABC-DEF-GHI or ABC-DEF, or a GameShark
+    // code, TTVVAAAA with the address in little endian.
+    pub fn parse(text: &str) -> Result<Code, String> {
+        let d = hex_digits(text)?;
+        match d.len() {
+            6 | 9 => {
+                // AB is the value and FCDE the address, with F inverted. The
+                // compare value is GI rotated right by 2, then XORed with
+                // 0xBA. H is not used.
+                let address = ((d[5] ^ 0xF) as u16) << 12 | (d[2] as u16) << 8 | (d[3] as u16) << 4 | d[4] as u16;
+                if address >= 0x8000 {
+                    return Err(format!("Game Genie codes only patch ROM, not {:04X}", address));
+                }
+                let compare = if d.len() == 9 {
+                    Some((d[6] << 4 | d[8]).rotate_right(2) ^ 0xBA)
+                } else {
+                    None
+                };
+                return Ok(Code::GameGenie(GameGenie {
+                    address,
+                    value: d[0] << 4 | d[1],
+                    compare,
+                }));
+            }
+            8 => {
+                let byte = |i: usize| d[i] << 4 | d[i + 1];
+                let address = (byte(6) as u16) << 8 | byte(4) as u16;
+                if address < 0x8000 {
+                    return Err(format!("GameShark codes only write to RAM, not {:04X}", address));
+                }
+                return Ok(Code::GameShark(GameShark {
+                    bank: byte(0),
+                    value: byte(2),
+                    address,
+                }));
+            }
+            _ => return Err(format!("{} is not a Game Genie or GameShark code", text)),
+        }
+    }
+}
+
+impl Cheat {
+    pub fn new(text: &str, name: &str) -> Result<Cheat, String> {
+        return Ok(Cheat {
+            code: Code::parse(text)?,
+            text: text.to_string(),
+            name: name.to_string(),
+            enabled: true,
+        });
+    }
+}
+
+// load_cheats reads a cheat file. Each line is + or - for whether the cheat
+// is enabled, the code, and its name:
+//
+//   + 01FF16D0 Infinite lives
+//   - 00A-17B-C49 Start with 99 coins
+//
+// Lines starting with # are comments. Invalid cheats are reported and skipped.
+pub fn load_cheats(path: &Path) -> io::Result<Vec<Cheat>> {
+    let text = fs::read_to_string(path)?;
+    let mut cheats = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.splitn(3, char::is_whitespace);
+        let enabled = fields.next() == Some("+");
+        let code = fields.next().unwrap_or("");
+        let name = fields.next().unwrap_or("").trim();
+        match Cheat::new(code, name) {
+            Ok(mut cheat) => {
+                cheat.enabled = enabled;
+                cheats.push(cheat);
+            }
+            Err(e) => println!("Invalid cheat on line {} of {}: {}", number + 1, path.display(), e),
+        }
+    }
+    return Ok(cheats);
+}
+
+pub fn save_cheats(path: &Path, cheats: &[Cheat]) -> io::Result<()> {
+    let mut text = String::new();
+    for cheat in cheats.iter() {
+        let enabled = if cheat.enabled { "+" } else { "-" };
+        text.push_str(&format!("{} {} {}\n", enabled, cheat.text, cheat.name));
+    }
+    return fs::write(path, text);
+}
+
+impl Gameboy {
+    pub fn set_cheats(&mut self, cheats: Vec<Cheat>) {
+        self.cheats = cheats;
+        self.update_game_genie();
+    }
+
+    // toggle_cheat enables or disables a cheat, returning whether it is now
+    // enabled.
+    pub fn toggle_cheat(&mut self, index: usize) -> Option<bool> {
+        let cheat = self.cheats.get_mut(index)?;
+        cheat.enabled = !cheat.enabled;
+        let enabled = cheat.enabled;
+        self.update_game_genie();
+        return Some(enabled);
+    }
+
+    // update_game_genie gives the cart the Game Genie codes that are enabled.
+    fn update_game_genie(&mut self) {
+        self.memory.cart.game_genie = self.cheats.iter()
+            .filter(|cheat| cheat.enabled)
+            .filter_map(|cheat| match cheat.code {
+                Code::GameGenie(code) => Some(code),
+                _ => None,
+            })
+            .collect();
+    }
+
+    // apply_game_shark writes the enabled GameShark codes, which is done
+    // once a frame at VBlank.
+    pub fn apply_game_shark(&mut self) {
+        for i in 0..self.cheats.len() {
+            if !self.cheats[i].enabled {
+                continue;
+            }
+            if let Code::GameShark(code) = self.cheats[i].code {
+                let banked = self.cgb_mode && code.bank & 0xF0 == 0x90 && (0xD000..=0xDFFF).contains(&code.address);
+                if banked {
+                    let bank = (code.bank & 0x7).max(1);
+                    self.memory.wram[wram_bank_index(code.address, bank)] = code.value;
+                } else {
+                    self.write(code.address, code.value);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cart::controller::Cart;
+    use crate::cheats::{load_cheats, save_cheats, Cheat, Code, GameGenie, GameShark};
+    use crate::gameboy::Gameboy;
+
+    fn gameboy(rom: Vec<u8>) -> Gameboy {
+        return Gameboy::from_cart(Cart::from_rom(rom, "").unwrap());
+    }
+
+    #[test]
+    fn parses_game_genie_codes() {
+        assert_eq!(Code::parse("3EA-17B-C49"), Ok(Code::GameGenie(GameGenie {
+            address: 0x4A17,
+            value: 0x3E,
+            compare: Some(0xC8),
+        })));
+        assert_eq!(Code::parse("3ea-17f"), Ok(Code::GameGenie(GameGenie {
+            address: 0x0A17,
+            value: 0x3E,
+            compare: None,
+        })));
+        assert!(Code::parse("3EA-170").is_err());
+    }
+
+    #[test]
+    fn parses_game_shark_codes() {
+        assert_eq!(Code::parse("0163A5C0"), Ok(Code::GameShark(GameShark {
+            bank: 0x01,
+            value: 0x63,
+            address: 0xC0A5,
+        })));
+        assert!(Code::parse("01630040").is_err());
+        assert!(Code::parse("0163A5C").is_err());
+    }
+
+    #[test]
+    fn game_genie_compares_with_rom() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x4A17] = 0xC8;
+        let mut gb = gameboy(rom);
+        gb.set_cheats(vec![
+            Cheat::new("3EA-17B-C49", "").unwrap(),
+            // This one expects a different byte, so it doesn't apply.
+            Cheat {
+                code: Code::GameGenie(GameGenie { address: 0x4A10, value: 0x3E, compare: Some(0x11) }),
+                text: String::new(),
+                name: String::new(),
+                enabled: true,
+            },
+        ]);
+        assert_eq!(gb.read(0x4A17), 0x3E);
+        assert_eq!(gb.read(0x4A10), 0x00);
+
+        assert_eq!(gb.toggle_cheat(0), Some(false));
+        assert_eq!(gb.read(0x4A17), 0xC8);
+    }
+
+    #[test]
+    fn game_shark_writes_at_vblank() {
+        let mut gb = gameboy(vec![0; 0x8000]);
+        gb.set_cheats(vec![Cheat::new("0163A5C0", "").unwrap()]);
+        assert_eq!(gb.read(0xC0A5), 0x00);
+        gb.apply_game_shark();
+        assert_eq!(gb.read(0xC0A5), 0x63);
+    }
+
+    #[test]
+    fn game_shark_writes_to_wram_banks() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x143] = 0xC0;
+        let mut gb = gameboy(rom);
+        gb.set_cheats(vec![Cheat::new("93420CD0", "").unwrap()]);
+        gb.apply_game_shark();
+        assert_eq!(gb.read(0xD00C), 0x00);
+        gb.write(0xFF70, 3);
+        assert_eq!(gb.read(0xD00C), 0x42);
+    }
+
+    #[test]
+    fn cheat_files_round_trip() {
+        let path = std::env::temp_dir().join("gameboy-cheats-test.cht");
+        let mut cheats = vec![
+            Cheat::new("0163A5C0", "Infinite lives").unwrap(),
+            Cheat::new("3EA-17B-C49", "Start with 99 coins").unwrap(),
+        ];
+        cheats[1].enabled = false;
+        save_cheats(&path, &cheats).unwrap();
+
+        let loaded = load_cheats(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[0].name, "Infinite lives");
+        assert!(loaded[0].enabled);
+        assert_eq!(loaded[1].text, "3EA-17B-C49");
+        assert!(!loaded[1].enabled);
+    }
+}
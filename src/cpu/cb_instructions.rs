@@ -117,10 +117,10 @@ impl Gameboy {
                 self.rlc("hl", false, self.cpu.hl.lo());
             }
             0x6 => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 let carry = addr >> 7;
                 let rot = (addr << 1) & 0xFF | carry;
-                self.write(self.cpu.hl.full(), rot);
+                self.write_cycle(self.cpu.hl.full(), rot);
                 self.cpu.set_flags(carry == 1, false, false, rot == 0);
             }
             0x7 => {
@@ -145,10 +145,10 @@ impl Gameboy {
                 self.rrc("hl", false, self.cpu.hl.lo());
             }
             0xe => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 let carry = addr & 1;
                 let rot = (addr >> 1) | (carry << 7);
-                self.write(self.cpu.hl.full(), rot);
+                self.write_cycle(self.cpu.hl.full(), rot);
                 self.cpu.set_flags(carry == 1, false, false, rot == 0);
             }
             0xf => {
@@ -173,11 +173,11 @@ impl Gameboy {
                 self.rl("hl", false, self.cpu.hl.lo());
             }
             0x16 => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 let carry = addr >> 7;
                 let prev_carry = b(self.cpu.c());
                 let rot = (addr << 1) & 0xFF | prev_carry;
-                self.write(self.cpu.hl.full(), rot);
+                self.write_cycle(self.cpu.hl.full(), rot);
                 self.cpu.set_flags(carry == 1, false, false, rot == 0);
             }
             0x17 => {
@@ -202,11 +202,11 @@ impl Gameboy {
                 self.rr("hl", false, self.cpu.hl.lo());
             }
             0x1e => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 let carry = addr & 1;
                 let prev_carry = b(self.cpu.c());
                 let rot = (addr >> 1) | (prev_carry << 7);
-                self.write(self.cpu.hl.full(), rot);
+                self.write_cycle(self.cpu.hl.full(), rot);
                 self.cpu.set_flags(carry == 1, false, false, rot == 0);
             }
             0x1f => {
@@ -231,10 +231,10 @@ impl Gameboy {
                 self.sla("hl", false, self.cpu.hl.lo());
             }
             0x26 => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 let carry = addr >> 7;
                 let rot = (addr << 1) & 0xFF;
-                self.write(self.cpu.hl.full(), rot);
+                self.write_cycle(self.cpu.hl.full(), rot);
                 self.cpu.set_flags(carry == 1, false, false, rot == 0);
             }
             0x27 => {
@@ -259,9 +259,9 @@ impl Gameboy {
                 self.sra("hl", false, self.cpu.hl.lo());
             }
             0x2e => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 let rot = (addr & 128) | (addr >> 1);
-                self.write(self.cpu.hl.full(), rot);
+                self.write_cycle(self.cpu.hl.full(), rot);
                 self.cpu.set_flags(addr & 1 == 1, false, false, rot == 0);
             }
             0x2f => {
@@ -286,9 +286,9 @@ impl Gameboy {
                 self.swap("hl", false, self.cpu.hl.lo());
             }
             0x36 => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 let swapped = addr << 4 & 240 | addr >> 4;
-                self.write(self.cpu.hl.full(), swapped);
+                self.write_cycle(self.cpu.hl.full(), swapped);
                 self.cpu.set_flags(false, false, false, swapped == 0);
             }
             0x37 => {
@@ -313,10 +313,10 @@ impl Gameboy {
                 self.srl("hl", false, self.cpu.hl.lo());
             }
             0x3e => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 let carry = addr & 1;
                 let rot = addr >> 1;
-                self.write(self.cpu.hl.full(), rot);
+                self.write_cycle(self.cpu.hl.full(), rot);
                 self.cpu.set_flags(carry == 1, false, false, rot == 0);
             }
             0x3f => {
@@ -341,7 +341,7 @@ impl Gameboy {
                 self.bit(0, self.cpu.hl.lo());
             }
             0x46 => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 self.bit(0, addr);
             }
             0x47 => {
@@ -366,7 +366,7 @@ impl Gameboy {
                 self.bit(1, self.cpu.hl.lo());
             }
             0x4e => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 self.bit(1, addr);
             }
             0x4f => {
@@ -391,7 +391,7 @@ impl Gameboy {
                 self.bit(2, self.cpu.hl.lo());
             }
             0x56 => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 self.bit(2, addr);
             }
             0x57 => {
@@ -416,7 +416,7 @@ impl Gameboy {
                 self.bit(3, self.cpu.hl.lo());
             }
             0x5e => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 self.bit(3, addr);
             }
             0x5f => {
@@ -441,7 +441,7 @@ impl Gameboy {
                 self.bit(4, self.cpu.hl.lo());
             }
             0x66 => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 self.bit(4, addr);
             }
             0x67 => {
@@ -466,7 +466,7 @@ impl Gameboy {
                 self.bit(5, self.cpu.hl.lo());
             }
             0x6e => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 self.bit(5, addr);
             }
             0x6f => {
@@ -491,7 +491,7 @@ impl Gameboy {
                 self.bit(6, self.cpu.hl.lo());
             }
             0x76 => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 self.bit(6, addr);
             }
             0x77 => {
@@ -516,7 +516,7 @@ impl Gameboy {
                 self.bit(7, self.cpu.hl.lo());
             }
             0x7e => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 self.bit(7, addr);
             }
             0x7f => {
@@ -547,9 +547,9 @@ impl Gameboy {
                 self.cpu.hl.set_lo(val);
             }
             0x86 => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 let val = reset(addr, 0);
-                self.write(self.cpu.hl.full(), val);
+                self.write_cycle(self.cpu.hl.full(), val);
             }
             0x87 => {
                 let val = reset(self.cpu.af.hi(), 0);
@@ -580,9 +580,9 @@ impl Gameboy {
                 self.cpu.hl.set_lo(val);
             }
             0x8e => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 let val = reset(addr, 1);
-                self.write(self.cpu.hl.full(), val);
+                self.write_cycle(self.cpu.hl.full(), val);
             }
             0x8f => {
                 let val = reset(self.cpu.af.hi(), 1);
@@ -613,9 +613,9 @@ impl Gameboy {
                 self.cpu.hl.set_lo(val);
             }
             0x96 => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 let val = reset(addr, 2);
-                self.write(self.cpu.hl.full(), val);
+                self.write_cycle(self.cpu.hl.full(), val);
             }
             0x97 => {
                 let val = reset(self.cpu.af.hi(), 2);
@@ -646,9 +646,9 @@ impl Gameboy {
                 self.cpu.hl.set_lo(val);
             }
             0x9e => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 let val = reset(addr, 3);
-                self.write(self.cpu.hl.full(), val);
+                self.write_cycle(self.cpu.hl.full(), val);
             }
             0x9f => {
                 let val = reset(self.cpu.af.hi(), 3);
@@ -679,9 +679,9 @@ impl Gameboy {
                 self.cpu.hl.set_lo(val);
             }
             0xa6 => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 let val = reset(addr, 4);
-                self.write(self.cpu.hl.full(), val);
+                self.write_cycle(self.cpu.hl.full(), val);
             }
             0xa7 => {
                 let val = reset(self.cpu.af.hi(), 4);
@@ -712,9 +712,9 @@ impl Gameboy {
                 self.cpu.hl.set_lo(val);
             }
             0xae => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 let val = reset(addr, 5);
-                self.write(self.cpu.hl.full(), val);
+                self.write_cycle(self.cpu.hl.full(), val);
             }
             0xaf => {
                 let val = reset(self.cpu.af.hi(), 5);
@@ -745,9 +745,9 @@ impl Gameboy {
                 self.cpu.hl.set_lo(val);
             }
             0xb6 => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 let val = reset(addr, 6);
-                self.write(self.cpu.hl.full(), val);
+                self.write_cycle(self.cpu.hl.full(), val);
             }
             0xb7 => {
                 let val = reset(self.cpu.af.hi(), 6);
@@ -778,9 +778,9 @@ impl Gameboy {
                 self.cpu.hl.set_lo(val);
             }
             0xbe => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 let val = reset(addr, 7);
-                self.write(self.cpu.hl.full(), val);
+                self.write_cycle(self.cpu.hl.full(), val);
             }
             0xbf => {
                 let val = reset(self.cpu.af.hi(), 7);
@@ -811,9 +811,9 @@ impl Gameboy {
                 self.cpu.hl.set_lo(val);
             }
             0xc6 => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 let val = set(addr, 0);
-                self.write(self.cpu.hl.full(), val);
+                self.write_cycle(self.cpu.hl.full(), val);
             }
             0xc7 => {
                 let val = set(self.cpu.af.hi(), 0);
@@ -844,9 +844,9 @@ impl Gameboy {
                 self.cpu.hl.set_lo(val);
             }
             0xce => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 let val = set(addr, 1);
-                self.write(self.cpu.hl.full(), val);
+                self.write_cycle(self.cpu.hl.full(), val);
             }
             0xcf => {
                 let val = set(self.cpu.af.hi(), 1);
@@ -877,9 +877,9 @@ impl Gameboy {
                 self.cpu.hl.set_lo(val);
             }
             0xd6 => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 let val = set(addr, 2);
-                self.write(self.cpu.hl.full(), val);
+                self.write_cycle(self.cpu.hl.full(), val);
             }
             0xd7 => {
                 let val = set(self.cpu.af.hi(), 2);
@@ -910,9 +910,9 @@ impl Gameboy {
                 self.cpu.hl.set_lo(val);
             }
             0xde => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 let val = set(addr, 3);
-                self.write(self.cpu.hl.full(), val);
+                self.write_cycle(self.cpu.hl.full(), val);
             }
             0xdf => {
                 let val = set(self.cpu.af.hi(), 3);
@@ -943,9 +943,9 @@ impl Gameboy {
                 self.cpu.hl.set_lo(val);
             }
             0xe6 => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 let val = set(addr, 4);
-                self.write(self.cpu.hl.full(), val);
+                self.write_cycle(self.cpu.hl.full(), val);
             }
             0xe7 => {
                 let val = set(self.cpu.af.hi(), 4);
@@ -976,9 +976,9 @@ impl Gameboy {
                 self.cpu.hl.set_lo(val);
             }
             0xee => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 let val = set(addr, 5);
-                self.write(self.cpu.hl.full(), val);
+                self.write_cycle(self.cpu.hl.full(), val);
             }
             0xef => {
                 let val = set(self.cpu.af.hi(), 5);
@@ -1009,9 +1009,9 @@ impl Gameboy {
                 self.cpu.hl.set_lo(val);
             }
             0xf6 => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 let val = set(addr, 6);
-                self.write(self.cpu.hl.full(), val);
+                self.write_cycle(self.cpu.hl.full(), val);
             }
             0xf7 => {
                 let val = set(self.cpu.af.hi(), 6);
@@ -1042,9 +1042,9 @@ impl Gameboy {
                 self.cpu.hl.set_lo(val);
             }
             0xfe => {
-                let addr = self.read(self.cpu.hl.full());
+                let addr = self.read_cycle(self.cpu.hl.full());
                 let val = set(addr, 7);
-                self.write(self.cpu.hl.full(), val);
+                self.write_cycle(self.cpu.hl.full(), val);
             }
             0xff => {
                 let val = set(self.cpu.af.hi(), 7);
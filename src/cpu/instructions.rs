@@ -1,16 +1,17 @@
 use crate::bit_functions::half_carry_add;
 use crate::gameboy::Gameboy;
+use crate::memory::DIV;
 
 // OPCODE_CYCLES is the number of self.cpu cycles for each normal opcode.
 pub(crate) const OPCODE_CYCLES: [u8; 256] = [
     1, 3, 2, 2, 1, 1, 2, 1, 5, 2, 2, 2, 1, 1, 2, 1, // 0;
-    0, 3, 2, 2, 1, 1, 2, 1, 3, 2, 2, 2, 1, 1, 2, 1, // 1;
+    1, 3, 2, 2, 1, 1, 2, 1, 3, 2, 2, 2, 1, 1, 2, 1, // 1;
     2, 3, 2, 2, 1, 1, 2, 1, 2, 2, 2, 2, 1, 1, 2, 1, // 2;
     2, 3, 2, 2, 3, 3, 3, 1, 2, 2, 2, 2, 1, 1, 2, 1, // 3;
     1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // 4;
     1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // 5;
     1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // 6;
-    2, 2, 2, 2, 2, 2, 0, 2, 1, 1, 1, 1, 1, 1, 2, 1, // 7;
+    2, 2, 2, 2, 2, 2, 1, 2, 1, 1, 1, 1, 1, 1, 2, 1, // 7;
     1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // 8;
     1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // 9;
     1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // a
@@ -21,6 +22,28 @@ pub(crate) const OPCODE_CYCLES: [u8; 256] = [
     3, 3, 2, 1, 0, 4, 2, 4, 3, 2, 4, 1, 0, 0, 2, 4, // f
 ]; //0  1  2  3  4  5  6  7  8  9  a  b  c  d  e  f
 
+// OPCODE_CYCLES_BRANCHED is the number of self.cpu cycles for each normal
+// opcode when a conditional jump, call or return takes its branch. Every
+// other opcode costs the same as in OPCODE_CYCLES.
+const OPCODE_CYCLES_BRANCHED: [u8; 256] = [
+    1, 3, 2, 2, 1, 1, 2, 1, 5, 2, 2, 2, 1, 1, 2, 1, // 0;
+    1, 3, 2, 2, 1, 1, 2, 1, 3, 2, 2, 2, 1, 1, 2, 1, // 1;
+    3, 3, 2, 2, 1, 1, 2, 1, 3, 2, 2, 2, 1, 1, 2, 1, // 2;
+    3, 3, 2, 2, 3, 3, 3, 1, 3, 2, 2, 2, 1, 1, 2, 1, // 3;
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // 4;
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // 5;
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // 6;
+    2, 2, 2, 2, 2, 2, 1, 2, 1, 1, 1, 1, 1, 1, 2, 1, // 7;
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // 8;
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // 9;
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // a
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // b
+    5, 3, 4, 4, 6, 4, 2, 4, 5, 4, 4, 0, 6, 6, 2, 4, // c
+    5, 3, 4, 0, 6, 4, 2, 4, 5, 4, 4, 0, 6, 0, 2, 4, // d
+    3, 3, 2, 0, 0, 4, 2, 4, 4, 1, 4, 0, 0, 0, 2, 4, // e
+    3, 3, 2, 1, 0, 4, 2, 4, 3, 2, 4, 1, 0, 0, 2, 4, // f
+]; //0  1  2  3  4  5  6  7  8  9  a  b  c  d  e  f
+
 // CBOPCODE_CYCLES is the number of self.cpu cycles for each CB opcode.
 const CBOPCODE_CYCLES: [u8; 256] = [
     2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 4, 2, // 0;
@@ -42,7 +65,11 @@ const CBOPCODE_CYCLES: [u8; 256] = [
 ]; //0  1  2  3  4  5  6  7  8  9  a  b  c  d  e  f
 
 impl Gameboy {
-
+    // branch_taken charges the cycles of a conditional jump, call or return
+    // whose condition was met in place of the untaken cycle count.
+    fn branch_taken(&mut self, code: u8) {
+        self.cpu.clock.m = OPCODE_CYCLES_BRANCHED[code as usize] as usize;
+    }
 
     pub fn find_op(&mut self, code: u8) {
         match code {
@@ -106,23 +133,23 @@ impl Gameboy {
             }
             0x0A => {
                 // LD A,(bc);
-                let val = self.read(self.cpu.bc.full());
+                let val = self.read_cycle(self.cpu.bc.full());
                 self.cpu.af.set_hi(val);
             }
             0x1A => {
                 // LD A,(DE);
-                let val = self.read(self.cpu.de.full());
+                let val = self.read_cycle(self.cpu.de.full());
                 self.cpu.af.set_hi(val);
             }
             0x7E => {
                 // LD A,(HL);
-                let val = self.read(self.cpu.hl.full());
+                let val = self.read_cycle(self.cpu.hl.full());
                 self.cpu.af.set_hi(val);
             }
             0xFA => {
                 // LD A,(nn);
                 let addr = self.pop_pc16();
-                let val = self.read(addr);
+                let val = self.read_cycle(addr);
                 self.cpu.af.set_hi(val);
             }
             0x3E => {
@@ -160,7 +187,7 @@ impl Gameboy {
             }
             0x46 => {
                 // LD B,(HL);
-                let val = self.read(self.cpu.hl.full());
+                let val = self.read_cycle(self.cpu.hl.full());
                 self.cpu.bc.set_hi(val);
             }
             0x4F => {
@@ -193,7 +220,7 @@ impl Gameboy {
             }
             0x4E => {
                 // LD C,(HL);
-                let val = self.read(self.cpu.hl.full());
+                let val = self.read_cycle(self.cpu.hl.full());
                 self.cpu.bc.set_lo(val);
             }
             0x57 => {
@@ -226,7 +253,7 @@ impl Gameboy {
             }
             0x56 => {
                 // LD D,(HL);
-                let val = self.read(self.cpu.hl.full());
+                let val = self.read_cycle(self.cpu.hl.full());
                 self.cpu.de.set_hi(val);
             }
             0x5F => {
@@ -259,7 +286,7 @@ impl Gameboy {
             }
             0x5E => {
                 // LD E,(HL);
-                let val = self.read(self.cpu.hl.full());
+                let val = self.read_cycle(self.cpu.hl.full());
                 self.cpu.de.set_lo(val);
             }
             0x67 => {
@@ -292,7 +319,7 @@ impl Gameboy {
             }
             0x66 => {
                 // LD H,(HL);
-                let val = self.read(self.cpu.hl.full());
+                let val = self.read_cycle(self.cpu.hl.full());
                 self.cpu.hl.set_hi(val);
             }
             0x6F => {
@@ -325,109 +352,110 @@ impl Gameboy {
             }
             0x6E => {
                 // LD L,(HL);
-                let val = self.read(self.cpu.hl.full());
+                let val = self.read_cycle(self.cpu.hl.full());
                 self.cpu.hl.set_lo(val);
             }
             0x77 => {
                 // LD (HL),A
                 let val = self.cpu.af.hi();
-                self.write(self.cpu.hl.full(), val);
+                self.write_cycle(self.cpu.hl.full(), val);
             }
             0x70 => {
                 // LD (HL),B
                 let val = self.cpu.bc.hi();
-                self.write(self.cpu.hl.full(), val);
+                self.write_cycle(self.cpu.hl.full(), val);
             }
             0x71 => {
                 // LD (HL),C
                 let val = self.cpu.bc.lo();
-                self.write(self.cpu.hl.full(), val);
+                self.write_cycle(self.cpu.hl.full(), val);
             }
             0x72 => {
                 // LD (HL),D
                 let val = self.cpu.de.hi();
-                self.write(self.cpu.hl.full(), val);
+                self.write_cycle(self.cpu.hl.full(), val);
             }
             0x73 => {
                 // LD (HL),E
                 let val = self.cpu.de.lo();
-                self.write(self.cpu.hl.full(), val);
+                self.write_cycle(self.cpu.hl.full(), val);
             }
             0x74 => {
                 // LD (HL),H
                 let val = self.cpu.hl.hi();
-                self.write(self.cpu.hl.full(), val);
+                self.write_cycle(self.cpu.hl.full(), val);
             }
             0x75 => {
                 // LD (HL),L
                 let val = self.cpu.hl.lo();
-                self.write(self.cpu.hl.full(), val);
+                self.write_cycle(self.cpu.hl.full(), val);
             }
             0x36 => {
                 // LD (HL),n 36;
                 let val = self.pop_pc();
-                self.write(self.cpu.hl.full(), val);
+                self.write_cycle(self.cpu.hl.full(), val);
             }
             0x02 => {
                 // LD (bc),A
                 let val = self.cpu.af.hi();
-                self.write(self.cpu.bc.full(), val);
+                self.write_cycle(self.cpu.bc.full(), val);
             }
             0x12 => {
                 // LD (DE),A
                 let val = self.cpu.af.hi();
-                self.write(self.cpu.de.full(), val);
+                self.write_cycle(self.cpu.de.full(), val);
             }
             0xEA => {
                 // LD (nn),A
                 let val = self.cpu.af.hi();
                 let addr = self.pop_pc16();
-                self.write(addr, val);
+                self.write_cycle(addr, val);
             }
             0xF2 => {
                 // LD A,(C);
-                let val = 0xFF00_u16.wrapping_add(self.cpu.bc.lo() as u16);
-                self.cpu.af.set_hi(self.read(val));
+                let addr = 0xFF00_u16.wrapping_add(self.cpu.bc.lo() as u16);
+                let val = self.read_cycle(addr);
+                self.cpu.af.set_hi(val);
             }
             0xE2 => {
                 // LD (C),A
                 let val = self.cpu.af.hi();
                 let mem = 0xFF00_u16.wrapping_add(self.cpu.bc.lo() as u16);
-                self.write(mem, val);
+                self.write_cycle(mem, val);
             }
             0x3A => {
                 // LDD A,(HL);
-                let val = self.read(self.cpu.hl.full());
+                let val = self.read_cycle_increment(self.cpu.hl.full());
                 self.cpu.af.set_hi(val);
                 self.cpu.hl.set_full(self.cpu.hl.full().wrapping_sub(1));
             }
             0x32 => {
                 // LDD (HL),A
                 let val = self.cpu.hl.full();
-                self.write(val, self.cpu.af.hi());
+                self.write_cycle(val, self.cpu.af.hi());
                 self.cpu.hl.set_full(self.cpu.hl.full().wrapping_sub(1));
             }
             0x2A => {
                 // LDI A,(HL);
-                let val = self.read(self.cpu.hl.full());
+                let val = self.read_cycle_increment(self.cpu.hl.full());
                 self.cpu.af.set_hi(val);
                 self.cpu.hl.set_full(self.cpu.hl.full().wrapping_add(1));
             }
             0x22 => {
                 // LDI (HL),A
                 let val = self.cpu.hl.full();
-                self.write(val, self.cpu.af.hi());
+                self.write_cycle(val, self.cpu.af.hi());
                 self.cpu.hl.set_full(self.cpu.hl.full().wrapping_add(1));
             }
             0xE0 => {
                 // LD (0xFF00+n),A
                 let val = 0xFF00_u16.wrapping_add(self.pop_pc() as u16);
-                self.write(val, self.cpu.af.hi());
+                self.write_cycle(val, self.cpu.af.hi());
             }
             0xF0 => {
                 // LD A,(0xFF00+n);
                 let addr = self.pop_pc() as u16;
-                let val = self.read_upper_ram(0xFF00_u16.wrapping_add(addr));
+                let val = self.read_cycle(0xFF00_u16.wrapping_add(addr));
                 self.cpu.af.set_hi(val);
             }
             // ========== 16 bit instructions ===========
@@ -466,8 +494,8 @@ impl Gameboy {
             0x08 => {
                 // LD (nn),SP
                 let address = self.pop_pc16();
-                self.write(address, self.cpu.sp.lo());
-                self.write(address.wrapping_add(1), self.cpu.sp.hi());
+                self.write_cycle(address, self.cpu.sp.lo());
+                self.write_cycle(address.wrapping_add(1), self.cpu.sp.hi());
             }
             0xF5 => {
                 // PUSH AF
@@ -543,10 +571,11 @@ impl Gameboy {
             }
             0x86 => {
                 // ADD A,(HL);
+                let val = self.read_cycle(self.cpu.hl.full());
                 self.cpu.add(
                     "af",
                     true,
-                    self.read(self.cpu.hl.full()),
+                    val,
                     self.cpu.af.hi(),
                     false,
                 );
@@ -593,10 +622,11 @@ impl Gameboy {
             }
             0x8E => {
                 // ADC A,(HL);
+                let val = self.read_cycle(self.cpu.hl.full());
                 self.cpu.add(
                     "af",
                     true,
-                    self.read(self.cpu.hl.full()),
+                    val,
                     self.cpu.af.hi(),
                     true,
                 );
@@ -643,11 +673,12 @@ impl Gameboy {
             }
             0x96 => {
                 // SUB A,(HL);
+                let val = self.read_cycle(self.cpu.hl.full());
                 self.cpu.sub(
                     "af",
                     true,
                     self.cpu.af.hi(),
-                    self.read(self.cpu.hl.full()),
+                    val,
                     false,
                 );
             }
@@ -693,11 +724,12 @@ impl Gameboy {
             }
             0x9E => {
                 // Sbc A,(HL);
+                let val = self.read_cycle(self.cpu.hl.full());
                 self.cpu.sub(
                     "af",
                     true,
                     self.cpu.af.hi(),
-                    self.read(self.cpu.hl.full()),
+                    val,
                     true,
                 );
             }
@@ -736,9 +768,10 @@ impl Gameboy {
             }
             0xA6 => {
                 // AND A,(HL);
+                let val = self.read_cycle(self.cpu.hl.full());
                 self
                     .cpu
-                    .and("af", true, self.read(self.cpu.hl.full()), self.cpu.af.hi());
+                    .and("af", true, val, self.cpu.af.hi());
             }
             0xE6 => {
                 // AND A,#
@@ -775,8 +808,9 @@ impl Gameboy {
             }
             0xB6 => {
                 // OR A,(HL);
+                let val = self.read_cycle(self.cpu.hl.full());
                 self.cpu
-                    .or("af", true, self.read(self.cpu.hl.full()), self.cpu.af.hi());
+                    .or("af", true, val, self.cpu.af.hi());
             }
             0xF6 => {
                 // OR A,#
@@ -813,7 +847,7 @@ impl Gameboy {
             }
             0xAE => {
                 // XOR A,(HL);
-                let a = self.read(self.cpu.hl.full());
+                let a = self.read_cycle(self.cpu.hl.full());
                 self.cpu.xor("af", true, a, self.cpu.af.hi());
             }
             0xEE => {
@@ -851,7 +885,8 @@ impl Gameboy {
             }
             0xBE => {
                 // CP A,(HL);
-                self.cpu.cp(self.read(self.cpu.hl.full()), self.cpu.af.hi());
+                let val = self.read_cycle(self.cpu.hl.full());
+                self.cpu.cp(val, self.cpu.af.hi());
             }
             0xFE => {
                 // CP A,#
@@ -889,9 +924,9 @@ impl Gameboy {
             0x34 => {
                 // INC (HL);
                 let addr = self.cpu.hl.full();
-                let byte = self.read(addr);
+                let byte = self.read_cycle(addr);
                 let result = byte.wrapping_add(1);
-                self.write(addr, result);
+                self.write_cycle(addr, result);
                 self.cpu.set_z(result == 0);
                 self.cpu.set_n(false);
                 self.cpu.set_h(half_carry_add(byte, 1));
@@ -927,10 +962,10 @@ impl Gameboy {
             0x35 => {
                 // DEC (HL);
                 let addr = self.cpu.hl.full();
-                let byte = self.read(addr);
+                let byte = self.read_cycle(addr);
 
                 let result = byte.wrapping_sub(1);
-                self.write(addr, result);
+                self.write_cycle(addr, result);
                 self.cpu.set_z(result == 0);
                 self.cpu.set_n(true);
                 self.cpu.set_h(byte & 0x0f == 0);
@@ -972,41 +1007,49 @@ impl Gameboy {
             }
             0x03 => {
                 // INC bc
+                self.increment_cycle(self.cpu.bc.full());
                 let val = self.cpu.inc_16(self.cpu.bc.full());
                 self.cpu.bc.set_full(val);
             }
             0x13 => {
                 // INC DE
+                self.increment_cycle(self.cpu.de.full());
                 let val = self.cpu.inc_16(self.cpu.de.full());
                 self.cpu.de.set_full(val);
             }
             0x23 => {
                 // INC HL
+                self.increment_cycle(self.cpu.hl.full());
                 let val = self.cpu.inc_16(self.cpu.hl.full());
                 self.cpu.hl.set_full(val);
             }
             0x33 => {
                 // INC SP
+                self.increment_cycle(self.cpu.sp.full());
                 let val = self.cpu.inc_16(self.cpu.sp.full());
                 self.cpu.sp.set_full(val);
             }
             0x0B => {
                 // DEC bc
+                self.increment_cycle(self.cpu.bc.full());
                 let val = self.cpu.dec_16(self.cpu.bc.full());
                 self.cpu.bc.set_full(val);
             }
             0x1B => {
                 // DEC DE
+                self.increment_cycle(self.cpu.de.full());
                 let val = self.cpu.dec_16(self.cpu.de.full());
                 self.cpu.de.set_full(val);
             }
             0x2B => {
                 // DEC HL
+                self.increment_cycle(self.cpu.hl.full());
                 let val = self.cpu.dec_16(self.cpu.hl.full());
                 self.cpu.hl.set_full(val);
             }
             0x3B => {
                 // DEC SP
+                self.increment_cycle(self.cpu.sp.full());
                 let val = self.cpu.dec_16(self.cpu.sp.full());
                 self.cpu.sp.set_full(val);
             }
@@ -1056,23 +1099,30 @@ impl Gameboy {
             }
             0x76 => {
                 // HALT
-                self.halted = true;
+                if !self.interrupts.ime && self.interrupts.pending() != 0 {
+                    // HALT bug: with IME off and an interrupt pending the CPU
+                    // does not halt and reads the next byte twice.
+                    self.cpu.halt_bug = true;
+                } else {
+                    self.cpu.halted = true;
+                }
             }
             0x10 => {
                 // STOP
-                self.halted = true;
-                if self.cgb_mode {
-                    self.check_speed();
-                }
                 self.pop_pc();
+                // DIV is reset when STOP is executed.
+                self.write(DIV, 0);
+                if !(self.cgb_mode && self.check_speed()) {
+                    self.cpu.stopped = true;
+                }
             }
             0xF3 => {
                 // DI
-                self.interrupts_on = false;
+                self.interrupts.disable();
             }
             0xFB => {
                 // EI
-                self.interrupts_enabling = true
+                self.interrupts.enable_delayed();
             }
             0x07 => {
                 // RLCA
@@ -1129,7 +1179,7 @@ impl Gameboy {
                 let next = self.pop_pc16();
                 if !self.cpu.z() {
                     self.cpu.jump(next);
-                    self.cpu.clock.t = self.cpu.clock.t.wrapping_add(4);
+                    self.branch_taken(code);
                 }
             }
             0xCA => {
@@ -1137,7 +1187,7 @@ impl Gameboy {
                 let next = self.pop_pc16();
                 if self.cpu.z() {
                     self.cpu.jump(next);
-                    self.cpu.clock.t = self.cpu.clock.t.wrapping_add(4);
+                    self.branch_taken(code);
                 }
             }
             0xD2 => {
@@ -1145,7 +1195,7 @@ impl Gameboy {
                 let next = self.pop_pc16();
                 if !self.cpu.c() {
                     self.cpu.jump(next);
-                    self.cpu.clock.t = self.cpu.clock.t.wrapping_add(4);
+                    self.branch_taken(code);
                 }
             }
             0xDA => {
@@ -1153,7 +1203,7 @@ impl Gameboy {
                 let next = self.pop_pc16();
                 if self.cpu.c() {
                     self.cpu.jump(next);
-                    self.cpu.clock.t = self.cpu.clock.t.wrapping_add(4);
+                    self.branch_taken(code);
                 }
             }
             0xE9 => {
@@ -1172,7 +1222,7 @@ impl Gameboy {
                 if !self.cpu.z() {
                     let addr = (self.cpu.pc as i32).wrapping_add(next as i32);
                     self.cpu.jump((addr) as u16);
-                    self.cpu.clock.t = self.cpu.clock.t.wrapping_add(4);
+                    self.branch_taken(code);
                 }
             }
             0x28 => {
@@ -1181,7 +1231,7 @@ impl Gameboy {
                 if self.cpu.z() {
                     let addr = (self.cpu.pc as i32).wrapping_add(next as i32);
                     self.cpu.jump((addr) as u16);
-                    self.cpu.clock.t = self.cpu.clock.t.wrapping_add(4);
+                    self.branch_taken(code);
                 }
             }
             0x30 => {
@@ -1190,7 +1240,7 @@ impl Gameboy {
                 if !self.cpu.c() {
                     let addr = (self.cpu.pc as i32).wrapping_add(next as i32);
                     self.cpu.jump((addr) as u16);
-                    self.cpu.clock.t = self.cpu.clock.t.wrapping_add(4);
+                    self.branch_taken(code);
                 }
             }
             0x38 => {
@@ -1199,7 +1249,7 @@ impl Gameboy {
                 if self.cpu.c() {
                     let addr = (self.cpu.pc as i32).wrapping_add(next as i32);
                     self.cpu.jump((addr) as u16);
-                    self.cpu.clock.t = self.cpu.clock.t.wrapping_add(4);
+                    self.branch_taken(code);
                 }
             }
             0xCD => {
@@ -1212,7 +1262,7 @@ impl Gameboy {
                 let next = self.pop_pc16();
                 if !self.cpu.z() {
                     self.call(next);
-                    self.cpu.clock.t = self.cpu.clock.t.wrapping_add(12);
+                    self.branch_taken(code);
                 }
             }
             0xCC => {
@@ -1220,7 +1270,7 @@ impl Gameboy {
                 let next = self.pop_pc16();
                 if self.cpu.z() {
                     self.call(next);
-                    self.cpu.clock.t = self.cpu.clock.t.wrapping_add(12);
+                    self.branch_taken(code);
                 }
             }
             0xD4 => {
@@ -1228,7 +1278,7 @@ impl Gameboy {
                 let next = self.pop_pc16();
                 if !self.cpu.c() {
                     self.call(next);
-                    self.cpu.clock.t = self.cpu.clock.t.wrapping_add(12);
+                    self.branch_taken(code);
                 }
             }
             0xDC => {
@@ -1236,7 +1286,7 @@ impl Gameboy {
                 let next = self.pop_pc16();
                 if self.cpu.c() {
                     self.call(next);
-                    self.cpu.clock.t = self.cpu.clock.t.wrapping_add(12);
+                    self.branch_taken(code);
                 }
             }
             0xC7 => {
@@ -1277,45 +1327,45 @@ impl Gameboy {
             }
             0xC0 => {
                 // RET NZ
+                self.tick(); // condition check
                 if !self.cpu.z() {
                     self.ret();
-                    self.cpu.clock.t = self.cpu.clock.t.wrapping_add(12);
+                    self.branch_taken(code);
                 }
             }
             0xC8 => {
                 // RET Z
+                self.tick(); // condition check
                 if self.cpu.z() {
                     self.ret();
-                    self.cpu.clock.t = self.cpu.clock.t.wrapping_add(12);
+                    self.branch_taken(code);
                 }
             }
             0xD0 => {
                 // RET NC
+                self.tick(); // condition check
                 if !self.cpu.c() {
                     self.ret();
-                    self.cpu.clock.t = self.cpu.clock.t.wrapping_add(12);
+                    self.branch_taken(code);
                 }
             }
             0xD8 => {
                 // RET C
+                self.tick(); // condition check
                 if self.cpu.c() {
                     self.ret();
-                    self.cpu.clock.t = self.cpu.clock.t.wrapping_add(12);
+                    self.branch_taken(code);
                 }
             }
             0xD9 => {
                 // RETI
                 self.ret();
-                self.interrupts_enabling = true
+                self.interrupts.enable();
             }
             0xCB => {
                 // CB
                 let next_inst = self.pop_pc();
-                self.cpu.clock.t = self
-                    .cpu
-                    .clock
-                    .t
-                    .wrapping_add((CBOPCODE_CYCLES[next_inst as usize] * 4) as usize);
+                self.cpu.clock.m = CBOPCODE_CYCLES[next_inst as usize] as usize;
                 self.find_cb_op(next_inst);
             }
             _ => {
@@ -1,13 +1,24 @@
 use crate::gameboy::Gameboy;
 use crate::memory::MemoryAddr;
 use crate::cpu::instructions::OPCODE_CYCLES;
+use crate::graphics::oam_bug::OamCorruption;
+
+// SPEED_SWITCH_CYCLES is the number of cycles the CPU is stalled for after
+// STOP switches between normal and double speed (2050 machine cycles).
+pub const SPEED_SWITCH_CYCLES: usize = 8200;
 
 mod cb_instructions;
 mod instructions;
 mod operations;
+#[cfg(any(test, feature = "test-bus"))]
+pub mod test_bus;
+#[cfg(test)]
+mod tests;
 
 pub struct Clock {
+    // The number of machine cycles the current instruction takes.
     pub m: usize,
+    // The number of cycles spent so far on the current instruction.
     pub t: usize,
 }
 
@@ -62,11 +73,22 @@ pub struct Z80 {
     pub hl: Register,
     pub pc: u16,
     pub sp: Register,
-    pub divider: usize,
 
     pub clock: Clock,
-    pub halt: u8,
-    pub stop: u8,
+
+    // Set by HALT until an interrupt is requested.
+    pub halted: bool,
+    // Set by STOP until a selected joypad line goes low.
+    pub stopped: bool,
+    // Set when HALT is executed with IME off and an interrupt already
+    // pending, which causes the next opcode fetch not to increment PC.
+    pub halt_bug: bool,
+    // The number of cycles left before the CPU resumes after a CGB speed
+    // switch.
+    pub stall: usize,
+    // The number of cycles left before the CPU resumes after a CGB HDMA
+    // transfer. Unlike a speed switch, the rest of the system keeps running.
+    pub dma_stall: usize,
 }
 
 impl Z80 {
@@ -78,17 +100,71 @@ impl Z80 {
             hl: Register::new(),
             pc: 0,
             sp: Register::new(),
-            divider: 0,
             clock: Clock { m: 0, t: 0 },
-            halt: 0,
-            stop: 0,
+            halted: false,
+            stopped: false,
+            halt_bug: false,
+            stall: 0,
+            dma_stall: 0,
         };
     }
 }
 
 impl Gameboy {
+    // read_cycle reads a byte on behalf of the CPU, spending one machine
+    // cycle on the access.
+    pub fn read_cycle(&mut self, addr: MemoryAddr) -> u8 {
+        self.tick();
+        self.corrupt_oam(addr, OamCorruption::Read);
+        return self.read_bus(addr);
+    }
+
+    // write_cycle writes a byte on behalf of the CPU, spending one machine
+    // cycle on the access.
+    pub fn write_cycle(&mut self, addr: MemoryAddr, value: u8) {
+        self.tick();
+        self.corrupt_oam(addr, OamCorruption::Write);
+        if self.memory.dma.blocks(addr) {
+            return;
+        }
+        self.write(addr, value);
+        self.bus.hook_write(addr, value);
+    }
+
+    // read_cycle_increment is read_cycle for instructions that increment or
+    // decrement the address register in the same cycle, like POP and LDI.
+    // This only differs from read_cycle in how it corrupts OAM on DMG.
+    pub fn read_cycle_increment(&mut self, addr: MemoryAddr) -> u8 {
+        self.tick();
+        self.corrupt_oam(addr, OamCorruption::ReadIncrease);
+        return self.read_bus(addr);
+    }
+
+    // read_bus reads a byte as seen by the CPU, which may be kept off the bus
+    // by OAM DMA.
+    fn read_bus(&mut self, addr: MemoryAddr) -> u8 {
+        let value = if !self.memory.dma.blocks(addr) {
+            self.read(addr)
+        } else if addr >= 0xFE00 {
+            // OAM itself reads 0xFF while the DMA is writing to it.
+            0xFF
+        } else {
+            self.memory.dma.byte
+        };
+        self.bus.hook_read(addr, value);
+        return value;
+    }
+
+    // increment_cycle spends the internal cycle of a 16 bit increment or
+    // decrement. The register is put on the address bus, so this can also
+    // corrupt OAM on DMG.
+    pub fn increment_cycle(&mut self, value: u16) {
+        self.tick();
+        self.corrupt_oam(value, OamCorruption::Write);
+    }
+
     pub fn pop_pc(&mut self) -> u8 {
-        let opcode = self.read(self.cpu.pc);
+        let opcode = self.read_cycle(self.cpu.pc);
         self.cpu.pc = self.cpu.pc.wrapping_add(1);
         return opcode;
     }
@@ -101,16 +177,19 @@ impl Gameboy {
 
     pub fn pop_stack(&mut self) -> u16 {
         let sp = self.cpu.sp.full();
-        let lo = self.read(sp) as u16;
-        let hi = (self.read(sp.wrapping_add(1)) as u16) << 8;
+        let lo = self.read_cycle_increment(sp) as u16;
+        let hi = (self.read_cycle_increment(sp.wrapping_add(1)) as u16) << 8;
         self.cpu.sp.set_full(self.cpu.sp.full().wrapping_add(2));
         return lo | hi;
     }
 
     pub fn push_stack(&mut self, addr: MemoryAddr) {
+        // The stack pointer is decremented in an internal cycle before
+        // the first byte is written.
         let sp = self.cpu.sp.full();
-        self.write(sp.wrapping_sub(1), ((addr & 0xFF00) >> 8) as u8);
-        self.write(sp.wrapping_sub(2), (addr & 0xFF) as u8);
+        self.increment_cycle(sp);
+        self.write_cycle(sp.wrapping_sub(1), ((addr & 0xFF00) >> 8) as u8);
+        self.write_cycle(sp.wrapping_sub(2), (addr & 0xFF) as u8);
         self.cpu.sp.set_full(self.cpu.sp.full().wrapping_sub(2));
     }
 
@@ -119,10 +198,23 @@ impl Gameboy {
         self.cpu.pc = next;
     }
 
+    // execute_next_opcode runs a single instruction and returns the number
+    // of cycles it took. Memory accesses tick the rest of the system as they
+    // happen; any internal cycles left over once the instruction is done are
+    // spent at the end so the total matches the opcode tables.
     pub fn execute_next_opcode(&mut self) -> usize {
-        let opcode = self.pop_pc();
-        self.cpu.clock.t = (OPCODE_CYCLES[opcode as usize] * 4) as usize;
+        self.cpu.clock.t = 0;
+        let opcode = self.read_cycle(self.cpu.pc);
+        if self.cpu.halt_bug {
+            self.cpu.halt_bug = false;
+        } else {
+            self.cpu.pc = self.cpu.pc.wrapping_add(1);
+        }
+        self.cpu.clock.m = OPCODE_CYCLES[opcode as usize] as usize;
         self.find_op(opcode);
+        while self.cpu.clock.t < self.cpu.clock.m * 4 {
+            self.tick();
+        }
         return self.cpu.clock.t;
     }
 
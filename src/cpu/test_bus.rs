@@ -0,0 +1,105 @@
+use std::sync::{Arc, Mutex};
+use crate::cart::controller::Cart;
+use crate::gameboy::Gameboy;
+use crate::memory::bus::{MemoryDevice, MemoryHook};
+use crate::memory::MemoryAddr;
+
+// BusCycle is what the CPU did with the bus during one machine cycle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BusCycle {
+    Read(MemoryAddr, u8),
+    Write(MemoryAddr, u8),
+    // A cycle spent without a memory access.
+    Internal,
+}
+
+// TestBus is a flat 64KB of memory plugged over the whole memory map, so the
+// CPU can run single instructions in isolation. It is also hooked to the bus
+// to record every machine cycle. Clones share the same memory and cycles, so
+// the test keeps one while the Gameboy holds the others.
+#[derive(Clone)]
+pub struct TestBus {
+    memory: Arc<Mutex<Vec<u8>>>,
+    cycles: Arc<Mutex<Vec<BusCycle>>>,
+}
+
+impl TestBus {
+    pub fn new() -> TestBus {
+        return TestBus {
+            memory: Arc::new(Mutex::new(vec![0; 0x10000])),
+            cycles: Arc::new(Mutex::new(Vec::new())),
+        };
+    }
+
+    pub fn get(&self, addr: MemoryAddr) -> u8 {
+        return self.memory.lock().unwrap()[addr as usize];
+    }
+
+    pub fn set(&self, addr: MemoryAddr, value: u8) {
+        self.memory.lock().unwrap()[addr as usize] = value;
+    }
+
+    // load copies bytes into memory starting at addr.
+    pub fn load(&self, addr: MemoryAddr, bytes: &[u8]) {
+        let start = addr as usize;
+        self.memory.lock().unwrap()[start..start + bytes.len()].copy_from_slice(bytes);
+    }
+
+    pub fn cycles(&self) -> Vec<BusCycle> {
+        return self.cycles.lock().unwrap().clone();
+    }
+
+    pub fn clear_cycles(&self) {
+        self.cycles.lock().unwrap().clear();
+    }
+
+    // record records the access made in the cycle that was just ticked.
+    fn record(&self, cycle: BusCycle) {
+        let mut cycles = self.cycles.lock().unwrap();
+        match cycles.last_mut() {
+            Some(last) if *last == BusCycle::Internal => *last = cycle,
+            _ => cycles.push(cycle),
+        }
+    }
+}
+
+impl MemoryDevice for TestBus {
+    fn read(&self, addr: MemoryAddr) -> u8 {
+        return self.get(addr);
+    }
+
+    fn write(&mut self, addr: MemoryAddr, value: u8) {
+        self.set(addr, value);
+    }
+}
+
+// Every cycle starts out as internal, and becomes a read or write if the CPU
+// accesses memory in it.
+impl MemoryHook for TestBus {
+    fn read(&mut self, addr: MemoryAddr, value: u8) {
+        self.record(BusCycle::Read(addr, value));
+    }
+
+    fn write(&mut self, addr: MemoryAddr, value: u8) {
+        self.record(BusCycle::Write(addr, value));
+    }
+
+    fn cycle(&mut self) {
+        self.cycles.lock().unwrap().push(BusCycle::Internal);
+    }
+}
+
+impl Gameboy {
+    // with_test_bus creates a DMG Gameboy without a cartridge whose CPU only
+    // sees the returned TestBus. The LCD is turned off so that it can't
+    // request interrupts while an instruction runs.
+    pub fn with_test_bus() -> (Gameboy, TestBus) {
+        let mut gb = Gameboy::from_cart(Cart::from_rom(vec![0; 0x8000], "").unwrap());
+        gb.write(0xFF40, 0x00);
+        gb.interrupts.requested = 0;
+        let bus = TestBus::new();
+        gb.bus.plug(0x0000, 0xFFFF, Box::new(bus.clone()));
+        gb.bus.add_hook(Box::new(bus.clone()));
+        return (gb, bus);
+    }
+}
@@ -0,0 +1,304 @@
+use crate::cpu::test_bus::{BusCycle, TestBus};
+use crate::cpu::Z80;
+use crate::gameboy::Gameboy;
+
+// flags returns the flags as a ZNHC string, with - for flags that are clear.
+fn flags(cpu: &mut Z80) -> String {
+    let mut flags = String::new();
+    flags.push(if cpu.z() { 'Z' } else { '-' });
+    flags.push(if cpu.n() { 'N' } else { '-' });
+    flags.push(if cpu.h() { 'H' } else { '-' });
+    flags.push(if cpu.c() { 'C' } else { '-' });
+    return flags;
+}
+
+fn cpu() -> Z80 {
+    let mut cpu = Z80::new();
+    cpu.init(false);
+    return cpu;
+}
+
+// run runs the instruction in code with the given A and flags, returning the
+// Gameboy and its bus afterwards.
+fn run(code: &[u8], a: u8, f: u8) -> (Gameboy, TestBus) {
+    let (mut gb, bus) = Gameboy::with_test_bus();
+    gb.cpu.pc = 0xC000;
+    gb.cpu.af.set_full((a as u16) << 8 | f as u16);
+    bus.load(0xC000, code);
+    gb.execute_next_opcode();
+    return (gb, bus);
+}
+
+#[test]
+fn add_sets_flags() {
+    let mut cpu = cpu();
+    cpu.add("af", true, 0x3A, 0xC6, false);
+    assert_eq!(cpu.af.hi(), 0x00);
+    assert_eq!(flags(&mut cpu), "Z-HC");
+
+    cpu.add("af", true, 0x3C, 0x12, false);
+    assert_eq!(cpu.af.hi(), 0x4E);
+    assert_eq!(flags(&mut cpu), "----");
+
+    cpu.add("bc", false, 0x0F, 0x01, false);
+    assert_eq!(cpu.bc.lo(), 0x10);
+    assert_eq!(flags(&mut cpu), "--H-");
+}
+
+#[test]
+fn add_with_carry() {
+    let mut cpu = cpu();
+    cpu.set_c(true);
+    cpu.add("af", true, 0xE1, 0x1E, true);
+    assert_eq!(cpu.af.hi(), 0x00);
+    assert_eq!(flags(&mut cpu), "Z-HC");
+
+    // The carry is only added for ADC.
+    cpu.set_c(true);
+    cpu.add("af", true, 0x01, 0x01, false);
+    assert_eq!(cpu.af.hi(), 0x02);
+}
+
+#[test]
+fn add_matches_reference() {
+    let mut cpu = cpu();
+    for a in 0..=0xFF {
+        for b in 0..=0xFF {
+            for &carry in [false, true].iter() {
+                cpu.set_c(carry);
+                cpu.add("af", true, a, b, true);
+                let sum = a as u16 + b as u16 + carry as u16;
+                assert_eq!(cpu.af.hi(), sum as u8);
+                assert_eq!(cpu.z(), sum as u8 == 0);
+                assert!(!cpu.n());
+                assert_eq!(cpu.h(), (a & 0xF) + (b & 0xF) + carry as u8 > 0xF);
+                assert_eq!(cpu.c(), sum > 0xFF);
+            }
+        }
+    }
+}
+
+#[test]
+fn sub_sets_flags() {
+    let mut cpu = cpu();
+    cpu.sub("af", true, 0x3E, 0x3E, false);
+    assert_eq!(cpu.af.hi(), 0x00);
+    assert_eq!(flags(&mut cpu), "ZN--");
+
+    cpu.sub("af", true, 0x3E, 0x0F, false);
+    assert_eq!(cpu.af.hi(), 0x2F);
+    assert_eq!(flags(&mut cpu), "-NH-");
+
+    cpu.sub("af", true, 0x3E, 0x40, false);
+    assert_eq!(cpu.af.hi(), 0xFE);
+    assert_eq!(flags(&mut cpu), "-N-C");
+}
+
+#[test]
+fn sub_matches_reference() {
+    let mut cpu = cpu();
+    for a in 0..=0xFF {
+        for b in 0..=0xFF {
+            for &carry in [false, true].iter() {
+                cpu.set_c(carry);
+                cpu.sub("af", true, a, b, true);
+                let difference = a as i16 - b as i16 - carry as i16;
+                assert_eq!(cpu.af.hi(), difference as u8);
+                assert_eq!(cpu.z(), difference as u8 == 0);
+                assert!(cpu.n());
+                assert_eq!(cpu.h(), ((a & 0xF) as i16) - ((b & 0xF) as i16) - (carry as i16) < 0);
+                assert_eq!(cpu.c(), difference < 0);
+            }
+        }
+    }
+}
+
+#[test]
+fn add_16_signed_uses_low_byte_carries() {
+    let mut cpu = cpu();
+    cpu.add_16_signed("hl", 0xFFF8, 2);
+    assert_eq!(cpu.hl.full(), 0xFFFA);
+    assert_eq!(flags(&mut cpu), "----");
+
+    // Flags come from adding the low byte, even for negative offsets.
+    cpu.add_16_signed("hl", 0x0001, -1);
+    assert_eq!(cpu.hl.full(), 0x0000);
+    assert_eq!(flags(&mut cpu), "--HC");
+
+    cpu.add_16_signed("hl", 0x00FF, 1);
+    assert_eq!(cpu.hl.full(), 0x0100);
+    assert_eq!(flags(&mut cpu), "--HC");
+
+    cpu.add_16_signed("hl", 0x1000, -0x80);
+    assert_eq!(cpu.hl.full(), 0x0F80);
+    assert_eq!(flags(&mut cpu), "----");
+}
+
+#[test]
+fn inc_and_dec_keep_carry() {
+    let mut cpu = cpu();
+    cpu.set_c(true);
+    cpu.inc("bc", true, 0xFF);
+    assert_eq!(cpu.bc.hi(), 0x00);
+    assert_eq!(flags(&mut cpu), "Z-HC");
+
+    cpu.inc("bc", true, 0x50);
+    assert_eq!(cpu.bc.hi(), 0x51);
+    assert_eq!(flags(&mut cpu), "---C");
+
+    cpu.set_c(false);
+    cpu.dec("de", false, 0x01);
+    assert_eq!(cpu.de.lo(), 0x00);
+    assert_eq!(flags(&mut cpu), "ZN--");
+
+    cpu.dec("de", false, 0x00);
+    assert_eq!(cpu.de.lo(), 0xFF);
+    assert_eq!(flags(&mut cpu), "-NH-");
+}
+
+#[test]
+fn daa_adjusts_after_add() {
+    // 0x45 + 0x38 = 0x7D, adjusted to 83.
+    let (mut gb, _) = run(&[0x27], 0x7D, 0x00);
+    assert_eq!(gb.cpu.af.hi(), 0x83);
+    assert_eq!(flags(&mut gb.cpu), "----");
+
+    // 0x99 + 0x01 = 0x9A, adjusted to 00 with a carry.
+    let (mut gb, _) = run(&[0x27], 0x9A, 0x00);
+    assert_eq!(gb.cpu.af.hi(), 0x00);
+    assert_eq!(flags(&mut gb.cpu), "Z--C");
+}
+
+#[test]
+fn daa_adjusts_after_sub() {
+    // 0x83 - 0x38 = 0x4B with a half carry, adjusted to 45.
+    let (mut gb, _) = run(&[0x27], 0x4B, 0x60);
+    assert_eq!(gb.cpu.af.hi(), 0x45);
+    assert_eq!(flags(&mut gb.cpu), "-N--");
+}
+
+// to_bcd returns value, which must be below 100, as binary coded decimal.
+fn to_bcd(value: u8) -> u8 {
+    return (value / 10) << 4 | value % 10;
+}
+
+// alu_then_daa runs opcode, an operation on A and B, followed by DAA.
+fn alu_then_daa(opcode: u8, a: u8, b: u8) -> Gameboy {
+    let (mut gb, bus) = Gameboy::with_test_bus();
+    gb.cpu.pc = 0xC000;
+    gb.cpu.af.set_hi(a);
+    gb.cpu.bc.set_hi(b);
+    bus.load(0xC000, &[opcode, 0x27]);
+    gb.execute_next_opcode();
+    gb.execute_next_opcode();
+    return gb;
+}
+
+#[test]
+fn daa_gives_decimal_results() {
+    for x in 0..100 {
+        for y in 0..100 {
+            // ADD A,B
+            let mut gb = alu_then_daa(0x80, to_bcd(x), to_bcd(y));
+            assert_eq!(gb.cpu.af.hi(), to_bcd((x + y) % 100), "{} + {}", x, y);
+            assert_eq!(gb.cpu.c(), x + y >= 100, "{} + {}", x, y);
+
+            // SUB A,B
+            let mut gb = alu_then_daa(0x90, to_bcd(x), to_bcd(y));
+            let difference = (x as i16 - y as i16).rem_euclid(100) as u8;
+            assert_eq!(gb.cpu.af.hi(), to_bcd(difference), "{} - {}", x, y);
+            assert_eq!(gb.cpu.c(), x < y, "{} - {}", x, y);
+        }
+    }
+}
+
+#[test]
+fn rotates_through_carry() {
+    let (mut gb, _) = Gameboy::with_test_bus();
+    gb.cpu.set_c(false);
+    gb.rl("bc", true, 0x80);
+    assert_eq!(gb.cpu.bc.hi(), 0x00);
+    assert_eq!(flags(&mut gb.cpu), "Z--C");
+    gb.rl("bc", true, 0x00);
+    assert_eq!(gb.cpu.bc.hi(), 0x01);
+    assert_eq!(flags(&mut gb.cpu), "----");
+
+    gb.cpu.set_c(true);
+    gb.rr("bc", false, 0x01);
+    assert_eq!(gb.cpu.bc.lo(), 0x80);
+    assert_eq!(flags(&mut gb.cpu), "---C");
+}
+
+#[test]
+fn rotates_without_carry() {
+    let (mut gb, _) = Gameboy::with_test_bus();
+    gb.cpu.set_c(true);
+    gb.rlc("de", true, 0x85);
+    assert_eq!(gb.cpu.de.hi(), 0x0B);
+    assert_eq!(flags(&mut gb.cpu), "---C");
+
+    gb.rrc("de", false, 0x01);
+    assert_eq!(gb.cpu.de.lo(), 0x80);
+    assert_eq!(flags(&mut gb.cpu), "---C");
+
+    gb.rrc("de", false, 0x00);
+    assert_eq!(gb.cpu.de.lo(), 0x00);
+    assert_eq!(flags(&mut gb.cpu), "Z---");
+}
+
+#[test]
+fn shifts() {
+    let (mut gb, _) = Gameboy::with_test_bus();
+    gb.sla("hl", true, 0x80);
+    assert_eq!(gb.cpu.hl.hi(), 0x00);
+    assert_eq!(flags(&mut gb.cpu), "Z--C");
+
+    // SRA keeps bit 7, SRL clears it.
+    gb.sra("hl", false, 0x81);
+    assert_eq!(gb.cpu.hl.lo(), 0xC0);
+    assert_eq!(flags(&mut gb.cpu), "---C");
+    gb.srl("hl", false, 0x81);
+    assert_eq!(gb.cpu.hl.lo(), 0x40);
+    assert_eq!(flags(&mut gb.cpu), "---C");
+}
+
+#[test]
+fn rla_clears_zero() {
+    // Unlike RL A, RLA always clears Z.
+    let (mut gb, _) = run(&[0x17], 0x80, 0x00);
+    assert_eq!(gb.cpu.af.hi(), 0x00);
+    assert_eq!(flags(&mut gb.cpu), "---C");
+
+    let (mut gb, _) = run(&[0xCB, 0x17], 0x80, 0x00);
+    assert_eq!(gb.cpu.af.hi(), 0x00);
+    assert_eq!(flags(&mut gb.cpu), "Z--C");
+}
+
+#[test]
+fn test_bus_records_cycles() {
+    // LD (HL),n
+    let (mut gb, bus) = run(&[0x36, 0x42], 0x00, 0x00);
+    gb.cpu.hl.set_full(0xD000);
+    gb.cpu.pc = 0xC000;
+    bus.clear_cycles();
+    assert_eq!(gb.execute_next_opcode(), 12);
+    assert_eq!(bus.cycles(), vec![
+        BusCycle::Read(0xC000, 0x36),
+        BusCycle::Read(0xC001, 0x42),
+        BusCycle::Write(0xD000, 0x42),
+    ]);
+
+    // PUSH BC
+    gb.cpu.pc = 0xC002;
+    gb.cpu.sp.set_full(0xE000);
+    gb.cpu.bc.set_full(0x1234);
+    bus.set(0xC002, 0xC5);
+    bus.clear_cycles();
+    assert_eq!(gb.execute_next_opcode(), 16);
+    assert_eq!(bus.cycles(), vec![
+        BusCycle::Read(0xC002, 0xC5),
+        BusCycle::Internal,
+        BusCycle::Write(0xDFFF, 0x12),
+        BusCycle::Write(0xDFFE, 0x34),
+    ]);
+}
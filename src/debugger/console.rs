@@ -0,0 +1,190 @@
+use crate::debugger::ram_search::{Comparison, Location, RamSearch, Region, Watch, WatchType};
+use crate::gameboy::Gameboy;
+
+// MAX_LISTED is how many candidates search list shows at most.
+const MAX_LISTED: usize = 32;
+
+const HELP: &str = "\
+search new            start a search with all of RAM as candidates
+search eq|ne|gt|lt    keep candidates that are unchanged, changed, increased or decreased
+search +N|-N          keep candidates that increased or decreased by N
+search value N        keep candidates that are now N
+search list           show the candidates
+watch [BANK:]ADDR [u8|u16|bcdN] [NAME]
+                      watch an address, as a byte, a little endian word or N bytes of BCD
+unwatch N             stop watching the Nth watch
+watches               show the watches and their values
+Numbers are decimal, or hex with a 0x prefix. Addresses are always hex.";
+
+// Console runs debugger commands typed in by the user, returning what to show
+// them.
+pub struct Console {
+    search: Option<RamSearch>,
+    watches: Vec<Watch>,
+}
+
+fn parse_number(text: &str) -> Result<u8, String> {
+    let number = if text.starts_with("0x") {
+        u8::from_str_radix(&text[2..], 16)
+    } else {
+        text.parse()
+    };
+    return number.map_err(|_| format!("{} is not a number from 0 to 255", text));
+}
+
+// parse_location reads an address in hex, with an optional bank in front of
+// it like 2:D012. WRAM banks past wram_banks don't exist.
+fn parse_location(text: &str, wram_banks: u8) -> Result<Location, String> {
+    let (bank, address) = match text.find(':') {
+        Some(index) => (parse_number(&text[..index])?, &text[index + 1..]),
+        None => (0, text),
+    };
+    let address = u16::from_str_radix(address.trim_start_matches("0x"), 16)
+        .map_err(|_| format!("{} is not an address", address))?;
+    let location = Location::at(address, bank).ok_or(format!("{:04X} is not in WRAM, HRAM or cart RAM", address))?;
+    if location.region == Region::Wram && location.bank > wram_banks {
+        return Err(format!("There is no WRAM bank {}, the highest is {}", location.bank, wram_banks));
+    }
+    return Ok(location);
+}
+
+fn parse_watch_type(text: &str) -> Option<WatchType> {
+    return match text {
+        "u8" => Some(WatchType::U8),
+        "u16" => Some(WatchType::U16),
+        _ if text.starts_with("bcd") => match text[3..].parse() {
+            Ok(bytes) if bytes > 0 => Some(WatchType::Bcd(bytes)),
+            _ => None,
+        },
+        _ => None,
+    };
+}
+
+impl Console {
+    pub fn new() -> Console {
+        return Console {
+            search: None,
+            watches: Vec::new(),
+        };
+    }
+
+    pub fn execute(&mut self, gb: &Gameboy, line: &str) -> String {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let result = match words.as_slice() {
+            [] => Ok(String::new()),
+            ["help"] => Ok(HELP.to_string()),
+            ["search", args @ ..] => self.search(gb, args),
+            ["watch", location, args @ ..] => self.watch(gb, location, args),
+            ["unwatch", index] => self.unwatch(index),
+            ["watches"] => Ok(self.show_watches(gb)),
+            _ => Err(format!("Unknown command {}, try help", line.trim())),
+        };
+        return result.unwrap_or_else(|e| e);
+    }
+
+    fn search(&mut self, gb: &Gameboy, args: &[&str]) -> Result<String, String> {
+        let comparison = match args {
+            ["new"] => {
+                let search = RamSearch::new(gb);
+                let count = search.candidates().len();
+                self.search = Some(search);
+                return Ok(format!("{} candidates", count));
+            }
+            ["list"] => return self.list_candidates(gb),
+            ["eq"] => Comparison::Unchanged,
+            ["ne"] => Comparison::Changed,
+            ["gt"] => Comparison::Increased,
+            ["lt"] => Comparison::Decreased,
+            ["value", n] => Comparison::Equals(parse_number(n)?),
+            [n] if n.starts_with('+') => Comparison::IncreasedBy(parse_number(&n[1..])?),
+            [n] if n.starts_with('-') => Comparison::DecreasedBy(parse_number(&n[1..])?),
+            _ => return Err("Unknown search, try help".to_string()),
+        };
+        let search = self.search.as_mut().ok_or("No search running, start one with search new")?;
+        search.filter(gb, comparison);
+        return Ok(format!("{} candidates", search.candidates().len()));
+    }
+
+    fn list_candidates(&self, gb: &Gameboy) -> Result<String, String> {
+        let search = self.search.as_ref().ok_or("No search running, start one with search new")?;
+        let snapshot = gb.ram_snapshot();
+        let mut lines: Vec<String> = search.candidates().iter().take(MAX_LISTED).map(|candidate| {
+            format!("{} = {:02X}", candidate.location, snapshot.get(candidate.location))
+        }).collect();
+        if search.candidates().len() > MAX_LISTED {
+            lines.push(format!("and {} more", search.candidates().len() - MAX_LISTED));
+        }
+        return Ok(lines.join("\n"));
+    }
+
+    fn watch(&mut self, gb: &Gameboy, location: &str, args: &[&str]) -> Result<String, String> {
+        let location = parse_location(location, gb.wram_banks())?;
+        // The type can be left out, in which case the rest is the name.
+        let (kind, name) = match args.split_first() {
+            Some((kind, name)) if parse_watch_type(kind).is_some() => (parse_watch_type(kind).unwrap(), name),
+            _ => (WatchType::U8, args),
+        };
+        let name = if name.is_empty() { location.to_string() } else { name.join(" ") };
+        self.watches.push(Watch { name, location, kind });
+        return Ok(format!("Watching {} as {}", location, self.watches.len() - 1));
+    }
+
+    fn unwatch(&mut self, index: &str) -> Result<String, String> {
+        let index: usize = index.parse().map_err(|_| format!("{} is not a watch", index))?;
+        if index >= self.watches.len() {
+            return Err(format!("There is no watch {}", index));
+        }
+        let watch = self.watches.remove(index);
+        return Ok(format!("Stopped watching {}", watch.name));
+    }
+
+    fn show_watches(&self, gb: &Gameboy) -> String {
+        let snapshot = gb.ram_snapshot();
+        return self.watches.iter().enumerate().map(|(i, watch)| {
+            format!("{}: {} ({}) = {}", i, watch.name, watch.location, watch.value(&snapshot))
+        }).collect::<Vec<String>>().join("\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cart::controller::Cart;
+    use crate::debugger::console::Console;
+    use crate::gameboy::Gameboy;
+
+    #[test]
+    fn finds_and_watches_a_value() {
+        let mut gb = Gameboy::from_cart(Cart::from_rom(vec![0; 0x8000], "").unwrap());
+        let mut console = Console::new();
+        assert_eq!(console.execute(&gb, "search gt"), "No search running, start one with search new");
+        console.execute(&gb, "search new");
+
+        gb.write(0xC200, 0x10);
+        assert_eq!(console.execute(&gb, "search +16"), "1 candidates");
+        assert_eq!(console.execute(&gb, "search list"), "C200 = 10");
+
+        console.execute(&gb, "watch C200 bcd2 Lives left");
+        console.execute(&gb, "watch 1:D000");
+        gb.write(0xC201, 0x02);
+        assert_eq!(console.execute(&gb, "watches"), "0: Lives left (C200) = 0210\n1: 1:D000 (1:D000) = 0 (0x00)");
+        console.execute(&gb, "unwatch 0");
+        assert_eq!(console.execute(&gb, "watches"), "0: 1:D000 (1:D000) = 0 (0x00)");
+        assert_eq!(console.execute(&gb, "watch 8000"), "8000 is not in WRAM, HRAM or cart RAM");
+    }
+
+    #[test]
+    fn rejects_missing_wram_banks() {
+        let dmg = Gameboy::from_cart(Cart::from_rom(vec![0; 0x8000], "").unwrap());
+        let mut console = Console::new();
+        assert_eq!(console.execute(&dmg, "watch 2:D000"), "There is no WRAM bank 2, the highest is 1");
+        assert_eq!(console.execute(&dmg, "watch 8:D000"), "There is no WRAM bank 8, the highest is 1");
+        assert_eq!(console.execute(&dmg, "watches"), "");
+
+        let mut rom = vec![0; 0x8000];
+        rom[0x143] = 0x80;
+        let cgb = Gameboy::from_cart(Cart::from_rom(rom, "").unwrap());
+        assert_eq!(console.execute(&cgb, "watch 8:D000"), "There is no WRAM bank 8, the highest is 7");
+        assert_eq!(console.execute(&cgb, "watch 7:D000"), "Watching 7:D000 as 0");
+        assert_eq!(console.execute(&cgb, "watches"), "0: 7:D000 (7:D000) = 0 (0x00)");
+    }
+}
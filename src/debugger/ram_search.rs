@@ -0,0 +1,318 @@
+use std::fmt;
+use crate::gameboy::Gameboy;
+use crate::memory::{wram_bank_index, MemoryAddr};
+
+// Region is a block of RAM that can be searched.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Region {
+    Wram,
+    Hram,
+    CartRam,
+}
+
+// Location is a byte of RAM, by the address the CPU sees it at and the bank
+// it is in. The bank is only used for WRAM from 0xD000 and cart RAM.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Location {
+    pub region: Region,
+    pub bank: u8,
+    pub address: MemoryAddr,
+}
+
+impl Location {
+    // at returns the location of an address in the given bank, if it is in
+    // searchable RAM.
+    pub fn at(address: MemoryAddr, bank: u8) -> Option<Location> {
+        let (region, bank) = match address {
+            0xA000..=0xBFFF => (Region::CartRam, bank),
+            0xC000..=0xCFFF => (Region::Wram, 0),
+            0xD000..=0xDFFF => (Region::Wram, bank.max(1)),
+            0xFF80..=0xFFFE => (Region::Hram, 0),
+            _ => return None,
+        };
+        return Some(Location { region, bank, address });
+    }
+
+    // next returns the location of the byte after this one in the same bank.
+    pub fn next(&self) -> Option<Location> {
+        let address = self.address.checked_add(1)?;
+        let next = Location::at(address, self.bank)?;
+        if next.region != self.region {
+            return None;
+        }
+        return Some(next);
+    }
+
+    fn banked(&self) -> bool {
+        return self.region == Region::CartRam || (self.region == Region::Wram && self.address >= 0xD000);
+    }
+}
+
+impl fmt::Display for Location {
+    // Banked locations are shown as bank:address, like 2:D012.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.banked() {
+            return write!(f, "{}:{:04X}", self.bank, self.address);
+        }
+        return write!(f, "{:04X}", self.address);
+    }
+}
+
+// RamSnapshot is a copy of all the RAM that can be searched, every bank
+// included.
+pub struct RamSnapshot {
+    wram: Vec<u8>,
+    hram: Vec<u8>,
+    cart_ram: Vec<u8>,
+    wram_banks: u8,
+}
+
+impl RamSnapshot {
+    // get returns the value of a location, or 0xFF if the cart has no RAM
+    // there.
+    pub fn get(&self, location: Location) -> u8 {
+        return match location.region {
+            Region::Wram if location.address < 0xD000 => self.wram[(location.address - 0xC000) as usize],
+            Region::Wram => self.wram[wram_bank_index(location.address, location.bank)],
+            Region::Hram => self.hram[(location.address - 0xFF80) as usize],
+            Region::CartRam => {
+                let index = location.bank as usize * 0x2000 + (location.address - 0xA000) as usize;
+                *self.cart_ram.get(index).unwrap_or(&0xFF)
+            }
+        };
+    }
+
+    // locations returns every location in the snapshot.
+    pub fn locations(&self) -> Vec<Location> {
+        let mut locations = Vec::new();
+        let mut add = |start: MemoryAddr, end: MemoryAddr, bank: u8| {
+            for address in start..=end {
+                locations.push(Location::at(address, bank).unwrap());
+            }
+        };
+        add(0xC000, 0xCFFF, 0);
+        for bank in 1..=self.wram_banks {
+            add(0xD000, 0xDFFF, bank);
+        }
+        add(0xFF80, 0xFFFE, 0);
+        for bank in 0..(self.cart_ram.len() / 0x2000) as u8 {
+            add(0xA000, 0xBFFF, bank);
+        }
+        return locations;
+    }
+}
+
+impl Gameboy {
+    pub fn ram_snapshot(&self) -> RamSnapshot {
+        return RamSnapshot {
+            wram: self.memory.wram.to_vec(),
+            hram: self.memory.ram[0x80..0xFF].to_vec(),
+            cart_ram: self.memory.cart.get_save_data(),
+            wram_banks: self.wram_banks(),
+        };
+    }
+
+    // wram_banks returns the highest bank of WRAM that can be switched in at
+    // 0xD000.
+    pub fn wram_banks(&self) -> u8 {
+        return if self.cgb_mode { 7 } else { 1 };
+    }
+}
+
+// Comparison is how a candidate's value now is compared with its value when
+// the search was last filtered.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Unchanged,
+    Changed,
+    Increased,
+    Decreased,
+    IncreasedBy(u8),
+    DecreasedBy(u8),
+    // The value is now this, whatever it was before.
+    Equals(u8),
+}
+
+impl Comparison {
+    fn matches(&self, previous: u8, value: u8) -> bool {
+        return match *self {
+            Comparison::Unchanged => value == previous,
+            Comparison::Changed => value != previous,
+            Comparison::Increased => value > previous,
+            Comparison::Decreased => value < previous,
+            Comparison::IncreasedBy(n) => value == previous.wrapping_add(n),
+            Comparison::DecreasedBy(n) => value == previous.wrapping_sub(n),
+            Comparison::Equals(n) => value == n,
+        };
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Candidate {
+    pub location: Location,
+    // The value when the search was last filtered.
+    pub previous: u8,
+}
+
+// RamSearch narrows down where a game keeps a value by repeatedly keeping
+// only the bytes that changed the way the value did.
+pub struct RamSearch {
+    candidates: Vec<Candidate>,
+}
+
+impl RamSearch {
+    // new starts a search with every byte of RAM as a candidate.
+    pub fn new(gb: &Gameboy) -> RamSearch {
+        let snapshot = gb.ram_snapshot();
+        let candidates = snapshot.locations().into_iter().map(|location| Candidate {
+            location,
+            previous: snapshot.get(location),
+        }).collect();
+        return RamSearch { candidates };
+    }
+
+    // filter keeps the candidates whose values changed as compared, and
+    // remembers their values for the next filter.
+    pub fn filter(&mut self, gb: &Gameboy, comparison: Comparison) {
+        let snapshot = gb.ram_snapshot();
+        self.candidates.retain(|candidate| comparison.matches(candidate.previous, snapshot.get(candidate.location)));
+        for candidate in self.candidates.iter_mut() {
+            candidate.previous = snapshot.get(candidate.location);
+        }
+    }
+
+    pub fn candidates(&self) -> &[Candidate] {
+        return &self.candidates;
+    }
+}
+
+// WatchType is how the bytes of a watch are shown. Values of more than one
+// byte are little endian.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WatchType {
+    U8,
+    U16,
+    // Binary coded decimal of the given number of bytes, 2 digits each.
+    Bcd(u8),
+}
+
+impl WatchType {
+    fn len(&self) -> usize {
+        return match *self {
+            WatchType::U8 => 1,
+            WatchType::U16 => 2,
+            WatchType::Bcd(bytes) => bytes as usize,
+        };
+    }
+}
+
+pub struct Watch {
+    pub name: String,
+    pub location: Location,
+    pub kind: WatchType,
+}
+
+impl Watch {
+    // bytes returns the bytes of the watch, first byte first. Bytes past the
+    // end of the region read 0.
+    pub fn bytes(&self, snapshot: &RamSnapshot) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut location = Some(self.location);
+        for _ in 0..self.kind.len() {
+            bytes.push(location.map_or(0, |location| snapshot.get(location)));
+            location = location.and_then(|location| location.next());
+        }
+        return bytes;
+    }
+
+    // value returns the watch's value, shown according to its type.
+    pub fn value(&self, snapshot: &RamSnapshot) -> String {
+        let bytes = self.bytes(snapshot);
+        return match self.kind {
+            WatchType::U8 => format!("{} (0x{:02X})", bytes[0], bytes[0]),
+            WatchType::U16 => {
+                let value = bytes[0] as u16 | (bytes[1] as u16) << 8;
+                format!("{} (0x{:04X})", value, value)
+            }
+            // Nibbles above 9 are shown as they are, which makes values that
+            // aren't BCD easy to spot.
+            WatchType::Bcd(_) => bytes.iter().rev().map(|byte| format!("{:02X}", byte)).collect(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cart::controller::Cart;
+    use crate::debugger::ram_search::{Comparison, Location, RamSearch, Region, Watch, WatchType};
+    use crate::gameboy::Gameboy;
+
+    fn gameboy() -> Gameboy {
+        // An MBC1 cart with RAM.
+        let mut rom = vec![0; 0x8000];
+        rom[0x147] = 0x02;
+        return Gameboy::from_cart(Cart::from_rom(rom, "").unwrap());
+    }
+
+    #[test]
+    fn locations_know_their_banks() {
+        assert_eq!(Location::at(0xC123, 3).unwrap().bank, 0);
+        assert_eq!(Location::at(0xD123, 0).unwrap().bank, 1);
+        assert_eq!(Location::at(0xB000, 2).unwrap().region, Region::CartRam);
+        assert_eq!(Location::at(0x8000, 0), None);
+        assert_eq!(Location::at(0xFFFE, 0).unwrap().next(), None);
+        assert_eq!(Location::at(0xD123, 2).unwrap().to_string(), "2:D123");
+        assert_eq!(Location::at(0xFF90, 0).unwrap().to_string(), "FF90");
+    }
+
+    #[test]
+    fn search_narrows_down_candidates() {
+        let mut gb = gameboy();
+        gb.write(0xC100, 5);
+        gb.write(0xFF90, 5);
+        let mut search = RamSearch::new(&gb);
+
+        gb.write(0xC100, 4);
+        gb.write(0xFF90, 3);
+        search.filter(&gb, Comparison::Decreased);
+        assert_eq!(search.candidates().len(), 2);
+
+        gb.write(0xC100, 3);
+        gb.write(0xFF90, 1);
+        search.filter(&gb, Comparison::DecreasedBy(1));
+        assert_eq!(search.candidates().len(), 1);
+        assert_eq!(search.candidates()[0].location.address, 0xC100);
+        assert_eq!(search.candidates()[0].previous, 3);
+
+        search.filter(&gb, Comparison::Unchanged);
+        assert_eq!(search.candidates().len(), 1);
+        search.filter(&gb, Comparison::Equals(4));
+        assert!(search.candidates().is_empty());
+    }
+
+    #[test]
+    fn search_covers_cart_ram_banks() {
+        let mut gb = gameboy();
+        let mut search = RamSearch::new(&gb);
+        // Enable RAM, select bank 2 and write to it.
+        gb.write(0x0000, 0x0A);
+        gb.write(0x6000, 0x01);
+        gb.write(0x4000, 0x02);
+        gb.write(0xA010, 0x42);
+        search.filter(&gb, Comparison::Changed);
+        assert_eq!(search.candidates().len(), 1);
+        assert_eq!(search.candidates()[0].location, Location::at(0xA010, 2).unwrap());
+    }
+
+    #[test]
+    fn watches_show_values() {
+        let mut gb = gameboy();
+        gb.write(0xC000, 0x34);
+        gb.write(0xC001, 0x12);
+        let watch = |kind| Watch { name: String::new(), location: Location::at(0xC000, 0).unwrap(), kind };
+        let snapshot = gb.ram_snapshot();
+        assert_eq!(watch(WatchType::U8).value(&snapshot), "52 (0x34)");
+        assert_eq!(watch(WatchType::U16).value(&snapshot), "4660 (0x1234)");
+        assert_eq!(watch(WatchType::Bcd(2)).value(&snapshot), "1234");
+    }
+}
@@ -1,32 +1,42 @@
-use crate::bit_functions::{reset, set, test};
+use crate::interrupts::InterruptController;
+use crate::serial::Serial;
 use crate::cpu::{Z80};
+use crate::cart::controller::Cart;
+use crate::cheats::Cheat;
 use crate::graphics::{ColorPixel, SCREEN_HEIGHT, SCREEN_WIDTH};
-use crate::memory::{DIV, MMU, TIMA, TMA, FRAMES_PER_SECOND, CLOCK_SPEED};
-use crate::graphics::{CGBPalette, PALETTE_BGB};
+use crate::memory::{MMU, FRAMES_PER_SECOND, CLOCK_SPEED};
+use crate::memory::bus::Bus;
+use crate::graphics::{CGBPalette, PALETTE_BGB, PPU};
 
 pub struct Gameboy {
+    // Decides which component answers each address.
+    pub bus: Bus,
     pub memory: MMU,
     pub cpu: Z80,
     // TODO: Sounds
     paused: bool,
 
+    pub ppu: PPU,
     pub working_screen: [[ColorPixel; SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize],
-    pub bg_priority: [[bool; SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize],
-
-    pub tile_scanline: [u8; SCREEN_WIDTH as usize],
-    pub scanline_counter: isize,
     pub cleared: bool,
 
     pub rendered_screen: [[ColorPixel; SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize],
+    // Set when the last frame was not shown because the LCD had just been
+    // turned on. rendered_screen still holds the frame before it, which is
+    // blank if the LCD was off across a frame, and frontends should keep
+    // showing that.
+    pub frame_skipped: bool,
 
-    pub interrupts_enabling: bool,
-    pub interrupts_on: bool,
-    pub halted: bool,
+    pub interrupts: InterruptController,
+    pub serial: Serial,
 
     pub cgb_mode: bool,
     pub current_palette: usize,
     pub bg_palette: CGBPalette,
     pub sprite_palette: CGBPalette,
+
+    pub cheats: Vec<Cheat>,
+
 }
 
 impl Gameboy {
@@ -37,142 +47,97 @@ impl Gameboy {
 
         let mut cycles = 0;
         while cycles < self.memory.speed.cycle_frames * (self.memory.speed.current as usize + 1) {
-            let mut cycles_op = 4;
-            if !self.halted {
-                cycles_op = self.execute_next_opcode();
-            }
-            cycles += cycles_op;
-            self.update_graphics(cycles_op as isize);
-            self.update_timers(cycles_op);
-            cycles += self.do_interrupts();
+            cycles += self.step();
         }
+        self.end_frame();
         return cycles;
     }
 
-    pub fn update_timers(&mut self, cycles: usize) {
-        self.divider_register(cycles);
-        if self.is_clock_enabled() {
-            self.memory.timer.value += cycles;
-
-            let freq = self.get_clock_freq_count();
-            while self.memory.timer.value >= freq {
-                self.memory.timer.value = self.memory.timer.value.wrapping_sub(freq);
-                let tima = self.read(TIMA);
-                if tima == 0xFF {
-                    self.memory.ram[TIMA.wrapping_sub(0xFF00) as usize] = self.read(TMA);
-                    self.request_interrupt(2);
-                } else {
-                    self.memory.ram[TIMA.wrapping_sub(0xFF00) as usize] = tima.wrapping_add(1);
-                }
-            }
+    // step runs a single instruction, or a single machine cycle while the
+    // CPU is halted or stalled, returning the number of cycles it took.
+    pub fn step(&mut self) -> usize {
+        if self.cpu.stopped {
+            // Nothing is clocked in STOP mode, including the LCD.
+            return 4;
+        }
+        if self.cpu.stall > 0 {
+            // The timers do not run while a speed switch settles.
+            self.cpu.stall -= 4;
+            self.update_graphics(4);
+            return 4;
         }
-    }
-
-    pub fn get_clock_freq_count(&self) -> usize {
-        return match self.get_clock_freq() {
-            0 => 1024,
-            1 => 16,
-            2 => 64,
-            _ => 256,
-        };
-    }
 
-    pub fn toggle_speed(&mut self, on: bool) {
-        let frames = if on {4 * CLOCK_SPEED / FRAMES_PER_SECOND} else {CLOCK_SPEED / FRAMES_PER_SECOND};
-        self.memory.speed.cycle_frames = frames;
-    }
+        if self.cpu.dma_stall > 0 {
+            self.cpu.dma_stall -= 4;
+            self.cpu.clock.t = 0;
+            self.tick();
+            return self.cpu.clock.t;
+        }
 
-    pub fn divider_register(&mut self, cycles: usize) {
-        self.cpu.divider += cycles;
-        if self.cpu.divider >= 255 {
-            self.cpu.divider -= 255;
-            self.memory.ram[(DIV - 0xFF00) as usize] =
-                self.memory.ram[(DIV - 0xFF00) as usize].wrapping_add(1);
+        let mut cycles;
+        if self.cpu.halted {
+            self.cpu.clock.t = 0;
+            self.tick();
+            cycles = self.cpu.clock.t;
+        } else {
+            cycles = self.execute_next_opcode();
         }
+        cycles += self.do_interrupts();
+        return cycles;
     }
 
-    pub fn request_interrupt(&mut self, interrupt: u8) {
-        let mut req = self.read_upper_ram(0xFF0F);
-        req = set(req, interrupt);
-        self.write(0xFF0F, req);
+    // tick runs the components clocked alongside the CPU for one machine
+    // cycle. The CPU ticks before each memory access so that timers and the
+    // LCD are seen in the state they are in at that exact cycle.
+    pub fn tick(&mut self) {
+        self.cpu.clock.t += 4;
+        self.bus.hook_cycle();
+        self.update_dma();
+        self.update_graphics(4);
+        self.update_timers(4);
+        self.update_serial(4);
     }
 
-    pub fn do_interrupts(&mut self) -> usize {
-        if self.interrupts_enabling {
-            self.interrupts_on = true;
-            self.interrupts_enabling = false;
-            return 0;
-        }
-        if !self.interrupts_on && !self.halted {
-            return 0;
-        }
-        let req = self.read_upper_ram(0xFF0F);
-        let enabled = self.read_upper_ram(0xFFFF);
-        if req > 0 {
-            for i in 0_u8..5 {
-                if test(req, i) && test(enabled, i) {
-                    self.service_interrupt(i);
-                    return 20;
-                }
-            }
-        }
-        return 0;
+    pub fn toggle_speed(&mut self, on: bool) {
+        let frames = if on {4 * CLOCK_SPEED / FRAMES_PER_SECOND} else {CLOCK_SPEED / FRAMES_PER_SECOND};
+        self.memory.speed.cycle_frames = frames;
     }
 
-    pub fn service_interrupt(&mut self, interrupt: u8) {
-        if !self.interrupts_on && self.halted {
-            self.halted = false;
-            return;
-        }
-        self.interrupts_on = false;
-        self.halted = false;
-
-        let mut req = self.read_upper_ram(0xFF0F);
-        req = reset(req, interrupt);
-        self.write(0xFF0F, req);
-
-        self.push_stack(self.cpu.pc);
-        self.cpu.pc = match interrupt {
-            0 => 0x40,
-            1 => 0x48,
-            2 => 0x50,
-            3 => 0x58,
-            4 => 0x60,
-            _ => 0x00, // Shouldnt happen
-        }
+    pub fn new(rom: &str) -> Result<Gameboy, String> {
+        return Cart::new(rom).map(Gameboy::from_cart);
     }
 
-    pub fn new(rom: &str) -> Gameboy {
+    pub fn from_cart(cart: Cart) -> Gameboy {
         let mut cpu = Z80::new();
-        let mut memory = MMU::new(rom);
+        let mut memory = MMU::new(cart);
         memory.init();
         let cgb_mode = memory.has_cgb_mode();
         cpu.init(cgb_mode);
         return Gameboy {
+            bus: Bus::new(),
             memory,
             cpu,
             paused: false,
+            ppu: PPU::new(),
             working_screen: [[ColorPixel {
                 r: 255,
                 b: 255,
                 g: 255,
             }; SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize],
-            bg_priority: [[false; SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize],
-            tile_scanline: [0; SCREEN_WIDTH as usize],
-            scanline_counter: 456,
             cleared: false,
             rendered_screen: [[ColorPixel {
                 r: 255,
                 b: 255,
                 g: 255,
             }; SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize],
-            interrupts_enabling: false,
-            interrupts_on: false,
-            halted: false,
+            frame_skipped: false,
+            interrupts: InterruptController::new(),
+            serial: Serial::new(),
             cgb_mode,
             current_palette: PALETTE_BGB as usize,
             bg_palette: CGBPalette::new(),
             sprite_palette: CGBPalette::new(),
+            cheats: Vec::new(),
         };
     }
 }
@@ -0,0 +1,83 @@
+use std::collections::VecDeque;
+
+// FifoPixel is a pixel waiting in one of the PPU's pixel FIFOs. Only the
+// colour index and attributes are stored, the palette is applied when the
+// pixel is shifted out to the screen.
+#[derive(Clone, Copy)]
+pub struct FifoPixel {
+    // The 2 bit colour index from the tile data.
+    pub color: u8,
+    // The CGB palette number, or for DMG sprites the OBP register (0 or 1).
+    pub palette: u8,
+    // For background pixels the CGB BG-to-OAM priority attribute, for sprite
+    // pixels the OBJ-to-BG priority attribute.
+    pub priority: bool,
+    // The OAM index of the sprite the pixel belongs to.
+    pub sprite: u8,
+}
+
+pub type PixelFifo = VecDeque<FifoPixel>;
+
+// FetchStep is the step the background fetcher is on. Each of the first
+// three steps takes two dots, while Push is retried every dot until the
+// background FIFO is empty.
+#[derive(Clone, Copy, PartialEq)]
+pub enum FetchStep {
+    Tile,
+    DataLow,
+    DataHigh,
+    Push,
+}
+
+// Fetcher fetches background and window tiles one row of 8 pixels at a
+// time for the background FIFO.
+pub struct Fetcher {
+    pub step: FetchStep,
+    // The dots spent on the current step.
+    pub dots: u8,
+    // The tile column being fetched, relative to the start of the line or
+    // the start of the window.
+    pub x: u8,
+    pub tile: u8,
+    pub attributes: u8,
+    pub low: u8,
+    pub high: u8,
+    // Whether the fetcher is fetching window tiles.
+    pub window: bool,
+}
+
+impl Fetcher {
+    pub fn new() -> Fetcher {
+        return Fetcher {
+            step: FetchStep::Tile,
+            dots: 0,
+            x: 0,
+            tile: 0,
+            attributes: 0,
+            low: 0,
+            high: 0,
+            window: false,
+        };
+    }
+
+    // reset restarts the fetcher at the first tile of the background or the
+    // window.
+    pub fn reset(&mut self, window: bool) {
+        self.step = FetchStep::Tile;
+        self.dots = 0;
+        self.x = 0;
+        self.window = window;
+    }
+}
+
+// Sprite is an OAM entry selected for the current line.
+#[derive(Clone, Copy)]
+pub struct Sprite {
+    pub index: u8,
+    pub y: u8,
+    pub x: u8,
+    pub tile: u8,
+    pub attributes: u8,
+    // Set once the sprite's pixels have been fetched into the sprite FIFO.
+    pub fetched: bool,
+}
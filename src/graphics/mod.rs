@@ -1,7 +1,12 @@
 use crate::gameboy::Gameboy;
+use crate::graphics::fifo::{Fetcher, PixelFifo, Sprite};
 
+mod fifo;
+pub mod oam_bug;
 mod palette;
 mod rendering;
+#[cfg(test)]
+mod tests;
 
 
 pub const PALETTE_GRAYSCALE: u8 = 0;
@@ -35,6 +40,80 @@ pub struct CGBPalette {
     pub inc: bool,
 }
 
+// PPU holds the state of the pixel processing unit between dots. The LCD
+// registers themselves live in the IO area of memory.
+pub struct PPU {
+    // The dot within the current line, from 0 to 455.
+    pub dot: usize,
+    // The line being drawn. LY follows it, except on line 153 where LY
+    // already reads 0 for most of the line.
+    pub line: u8,
+    // The current mode, as shown in the lower bits of STAT.
+    pub mode: u8,
+    pub fetcher: Fetcher,
+    pub bg_fifo: PixelFifo,
+    pub sprite_fifo: PixelFifo,
+    // The x position of the next pixel to be shifted out to the screen.
+    pub lx: u8,
+    // The background pixels still to be dropped for SCX fine scrolling.
+    pub discard: u8,
+    // Set until the fetcher's first tile of the line has been thrown away.
+    pub first_fetch: bool,
+    // The sprites found by the OAM scan for the current line.
+    pub line_sprites: Vec<Sprite>,
+    // The sprite being fetched, as an index into line_sprites, and the dots
+    // left until its pixels are in the sprite FIFO.
+    pub sprite_fetch: Option<(usize, u8)>,
+    // Whether the window is being drawn on the current line.
+    pub window_active: bool,
+    // Whether the window was drawn at all on the current line, even if it
+    // has since been turned off. This is what advances window_line.
+    pub window_drawn: bool,
+    // Set once LY has matched WY in the current frame, which is required for
+    // the window to be drawn.
+    pub window_y_triggered: bool,
+    // The window's internal line counter. It only advances on lines where
+    // the window was drawn, so hiding the window for a few lines does not
+    // skip any of its rows.
+    pub window_line: u8,
+    // Set when WX is 166, which makes the window cover the next line.
+    pub window_next_line: bool,
+    // The STAT interrupt line, the OR of all enabled STAT sources. The
+    // interrupt is only requested when it goes from low to high, so one
+    // source being active blocks interrupts from the others.
+    pub stat_line: bool,
+    // Set when the LCD has just been turned on, until the start of line 0.
+    pub lcd_starting: bool,
+    // Set when the LCD has been turned on, until the end of the next frame.
+    pub skip_frame: bool,
+}
+
+impl PPU {
+    pub fn new() -> PPU {
+        return PPU {
+            dot: 0,
+            line: 0,
+            mode: 0,
+            fetcher: Fetcher::new(),
+            bg_fifo: PixelFifo::with_capacity(16),
+            sprite_fifo: PixelFifo::with_capacity(8),
+            lx: 0,
+            discard: 0,
+            first_fetch: true,
+            line_sprites: Vec::with_capacity(10),
+            sprite_fetch: None,
+            window_active: false,
+            window_drawn: false,
+            window_y_triggered: false,
+            window_line: 0,
+            window_next_line: false,
+            stat_line: false,
+            lcd_starting: false,
+            skip_frame: false,
+        };
+    }
+}
+
 impl Gameboy {
     pub fn swap_palette(&mut self) {
         self.current_palette = (self.current_palette + 1) % 3;
@@ -0,0 +1,82 @@
+use crate::gameboy::Gameboy;
+use crate::memory::MemoryAddr;
+
+// OAM_ROWS is the number of 8 byte rows OAM is split into. The PPU reads a
+// row every machine cycle of the OAM scan.
+const OAM_ROWS: usize = 20;
+
+// OamCorruption is the kind of access that put an OAM address on the bus
+// while the PPU was scanning OAM.
+#[derive(Clone, Copy, PartialEq)]
+pub enum OamCorruption {
+    Read,
+    // A write, or the internal cycle of a 16 bit increment or decrement.
+    Write,
+    // A read where the address register is incremented or decremented in
+    // the same cycle, as done by POP and LDI/LDD.
+    ReadIncrease,
+}
+
+impl Gameboy {
+    // corrupt_oam emulates the DMG OAM bug. Accessing 0xFE00 to 0xFEFF during
+    // the OAM scan, even just by having it in a register being incremented,
+    // mixes the row the PPU is reading with the row before it.
+    pub fn corrupt_oam(&mut self, addr: MemoryAddr, kind: OamCorruption) {
+        if self.cgb_mode || self.ppu.mode != 2 || addr < 0xFE00 || addr > 0xFEFF {
+            return;
+        }
+        let row = self.ppu.dot / 4;
+        if row == 0 || row >= OAM_ROWS {
+            return;
+        }
+
+        match kind {
+            OamCorruption::Write => {
+                let a = self.oam_word(row, 0);
+                let b = self.oam_word(row - 1, 0);
+                let c = self.oam_word(row - 1, 2);
+                self.set_oam_word(row, 0, ((a ^ c) & (b ^ c)) ^ c);
+                self.copy_oam_row(row - 1, row, 1);
+            }
+            OamCorruption::Read => self.corrupt_oam_read(row),
+            OamCorruption::ReadIncrease => {
+                if row >= 4 && row < OAM_ROWS - 1 {
+                    let a = self.oam_word(row - 2, 0);
+                    let b = self.oam_word(row - 1, 0);
+                    let c = self.oam_word(row, 0);
+                    let d = self.oam_word(row - 1, 2);
+                    self.set_oam_word(row - 1, 0, (b & (a | c | d)) | (a & c & d));
+                    self.copy_oam_row(row - 1, row - 2, 0);
+                    self.copy_oam_row(row - 1, row, 0);
+                }
+                self.corrupt_oam_read(row);
+            }
+        }
+    }
+
+    fn corrupt_oam_read(&mut self, row: usize) {
+        let a = self.oam_word(row, 0);
+        let b = self.oam_word(row - 1, 0);
+        let c = self.oam_word(row - 1, 2);
+        self.set_oam_word(row, 0, b | (a & c));
+        self.copy_oam_row(row - 1, row, 1);
+    }
+
+    fn oam_word(&self, row: usize, word: usize) -> u16 {
+        let index = row * 8 + word * 2;
+        return self.memory.oam[index] as u16 | (self.memory.oam[index + 1] as u16) << 8;
+    }
+
+    fn set_oam_word(&mut self, row: usize, word: usize, value: u16) {
+        let index = row * 8 + word * 2;
+        self.memory.oam[index] = value as u8;
+        self.memory.oam[index + 1] = (value >> 8) as u8;
+    }
+
+    // copy_oam_row copies the words of a row from the given word onwards.
+    fn copy_oam_row(&mut self, from: usize, to: usize, first_word: usize) {
+        for i in (first_word * 2)..8 {
+            self.memory.oam[to * 8 + i] = self.memory.oam[from * 8 + i];
+        }
+    }
+}
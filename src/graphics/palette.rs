@@ -47,12 +47,18 @@ impl CGBPalette {
 
     pub fn write(&mut self, value: u8) {
         self.palette[self.index as usize] = value;
+        self.increment();
+    }
+
+    // increment advances the index after a write if auto increment is on.
+    // This also happens when the write itself is blocked during mode 3.
+    pub fn increment(&mut self) {
         if self.inc {
             self.index = self.index.wrapping_add(1) & 0x3F;
         }
     }
 
-    pub fn get(&mut self, palette: u8, num: u8) -> ColorPixel {
+    pub fn get(&self, palette: u8, num: u8) -> ColorPixel {
         let index = (palette * 8) + (num * 2);
         // println!("index: {}", index);
         let color = self.palette[index as usize] as u16 | ((self.palette[(index+1) as usize] as u16) << 8);
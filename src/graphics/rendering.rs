@@ -1,330 +1,578 @@
 use crate::gameboy::Gameboy;
+use crate::interrupts::{LCD_STAT, VBLANK};
 use crate::bit_functions::{reset, set, test, val};
 use crate::graphics::{ColorPixel, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::graphics::fifo::{FetchStep, FifoPixel, Sprite};
 
 const LCDC: u16 = 0xFF40;
-const LCD_MODE_2_BOUNDS: isize = 456- 80;
-const LCD_MODE_3_BOUNDS: isize = LCD_MODE_2_BOUNDS - 172;
-const SPRITE_PRIORITY_OFFSET: isize = 100;
-
-pub struct TileSettings {
-    using_window: bool,
-    unsigned: bool,
-    tile_data: u16,
-    background_memory: u16,
-}
-
+const LINE_DOTS: usize = 456;
+const OAM_SCAN_DOTS: usize = 80;
+const LAST_LINE: u8 = 153;
+// LINE_153_LY_DOTS is the number of dots LY reads 153 for on the last line
+// before wrapping around to 0.
+const LINE_153_LY_DOTS: usize = 4;
+// SPRITE_FETCH_DOTS is the number of dots the background is paused for
+// while a sprite is fetched, once the background fetcher has its tile ready.
+const SPRITE_FETCH_DOTS: u8 = 6;
 
 impl Gameboy {
-    pub fn update_graphics(&mut self, cycles: isize) {
-        self.set_lcd_status();
-
+    pub fn update_graphics(&mut self, cycles: usize) {
         if !self.is_lcd_enabled() {
-            return
+            return;
         }
 
+        // In double speed mode the PPU runs at half the rate of the CPU.
+        let dots = cycles >> self.memory.speed.current;
+        for _ in 0..dots {
+            self.step_ppu();
+        }
+    }
 
-        self.scanline_counter -= cycles;
-
-        if self.scanline_counter <=0 {
-            self.memory.ram[0x44] = self.memory.ram[0x44].wrapping_add(1);
-            if self.memory.ram[0x44] > 153 {
-                self.rendered_screen.clone_from_slice(&self.working_screen);
-                self.working_screen = [[ColorPixel { r: 255, g: 255, b:255 }; SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize];
-                self.bg_priority = [[false; SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize];
-                self.memory.ram[0x44] = 0;
+    // step_ppu advances the PPU by a single dot.
+    fn step_ppu(&mut self) {
+        let line = self.ppu.line;
+        if line < SCREEN_HEIGHT {
+            if self.ppu.dot == 0 {
+                if self.ppu.lcd_starting {
+                    // The first line after the LCD is turned on has no OAM
+                    // scan, STAT reads mode 0 until pixel transfer starts.
+                    self.ppu.lcd_starting = false;
+                } else {
+                    self.set_mode(2);
+                }
+                self.ppu.line_sprites.clear();
+                if line == 0 {
+                    self.ppu.window_y_triggered = false;
+                    self.ppu.window_line = 0;
+                    self.ppu.window_next_line = false;
+                }
+                if line == self.memory.ram[0x4A] {
+                    self.ppu.window_y_triggered = true;
+                }
+            } else if self.ppu.dot == OAM_SCAN_DOTS {
+                self.set_mode(3);
+                self.start_pixel_transfer();
             }
 
-            let current = self.read_upper_ram(0xFF44);
-            self.scanline_counter += 456 * (self.memory.speed.current as isize + 1);
+            if self.ppu.mode == 2 && self.ppu.dot % 2 == 0 {
+                self.scan_oam_entry(line, self.ppu.dot / 2);
+            } else if self.ppu.mode == 3 {
+                self.step_pixel_transfer(line);
+                if self.ppu.lx == SCREEN_WIDTH {
+                    if self.ppu.window_drawn {
+                        self.ppu.window_line = self.ppu.window_line.wrapping_add(1);
+                    }
+                    self.set_mode(0);
+                }
+            }
+        }
 
-            if current == SCREEN_HEIGHT {
-                self.request_interrupt(0);
+        self.ppu.dot += 1;
+        if self.ppu.dot == LINE_DOTS {
+            self.ppu.dot = 0;
+            self.ppu.line = if line == LAST_LINE { 0 } else { line + 1 };
+            self.memory.ram[0x44] = self.ppu.line;
+            if self.ppu.line == SCREEN_HEIGHT {
+                self.set_mode(1);
+                self.request_interrupt(VBLANK);
+                self.apply_game_shark();
+                // The first frame after the LCD is turned on is not shown.
+                self.frame_skipped = self.ppu.skip_frame;
+                if !self.ppu.skip_frame {
+                    self.rendered_screen.clone_from_slice(&self.working_screen);
+                }
+                self.ppu.skip_frame = false;
             }
+            self.compare_ly();
+        } else if line == LAST_LINE && self.ppu.dot == LINE_153_LY_DOTS {
+            // LY wraps around to 0 early, so LY=LYC matches 0 during line 153.
+            self.memory.ram[0x44] = 0;
+            self.compare_ly();
         }
     }
 
-    pub fn set_lcd_status(&mut self) {
-        let mut status = self.read_upper_ram(0xFF41);
+    fn set_mode(&mut self, mode: u8) {
+        self.ppu.mode = mode;
+        self.memory.ram[0x41] = (self.memory.ram[0x41] & !0x3) | mode;
+        self.update_stat_line();
 
-        if !self.is_lcd_enabled() {
-            self.clear_screen();
-            self.scanline_counter = 456;
-            self.memory.ram[0x44] = 0;
-            status &= 252;
-            status = reset(status, 0);
-            status = reset(status, 1);
-            self.write(0xFF41, status);
-            return
+        if mode == 0 {
+            self.hdma_transfer();
         }
+    }
 
-        self.cleared = false;
-        let current_line = self.read_upper_ram(0xFF44);
-        let current_mode = status & 0x3;
-
-        let mode: u8;
-        let request_interrupt: bool;
-        if current_line >= 144 {
-            mode = 1;
-            status = set(status, 0);
-            status = reset(status, 1);
-            request_interrupt = test(status, 4);
-        } else if self.scanline_counter >= LCD_MODE_2_BOUNDS {
-            mode = 2;
-            status = reset(status, 0);
-            status = set(status, 1);
-            request_interrupt = test(status, 5);
-        } else if self.scanline_counter >= LCD_MODE_3_BOUNDS {
-            mode = 3;
-            status = set(status, 0);
-            status = set(status, 1);
-            request_interrupt = false;
-            if mode != current_mode {
-                self.draw_scanline(current_line);
-            }
+    fn compare_ly(&mut self) {
+        let status = self.memory.ram[0x41];
+        self.memory.ram[0x41] = if self.memory.ram[0x44] == self.memory.ram[0x45] {
+            set(status, 2)
         } else {
-            mode = 0;
-            status = reset(status, 0);
-            status = reset(status, 1);
-            request_interrupt = test(status, 3);
-            if mode != current_mode {
-                self.hdma_transfer();
-            }
+            reset(status, 2)
+        };
+        self.update_stat_line();
+    }
+
+    // stat_sources returns whether any of the STAT interrupt sources enabled
+    // in status is active.
+    fn stat_sources(&self, status: u8) -> bool {
+        let mode = match self.ppu.mode {
+            0 => test(status, 3),
+            // The OAM source also fires as VBlank starts on line 144.
+            1 => test(status, 4) || (self.ppu.line == SCREEN_HEIGHT && self.ppu.dot == 0 && test(status, 5)),
+            2 => test(status, 5),
+            _ => false,
+        };
+        return mode || (test(status, 2) && test(status, 6));
+    }
+
+    // update_stat_line recalculates the STAT interrupt line, requesting the
+    // interrupt on a rising edge.
+    fn update_stat_line(&mut self) {
+        let line = self.stat_sources(self.memory.ram[0x41]);
+        if line && !self.ppu.stat_line {
+            self.request_interrupt(LCD_STAT);
         }
+        self.ppu.stat_line = line;
+    }
 
-        if request_interrupt && mode != current_mode {
-            self.request_interrupt(1);
+    // write_stat handles writes to STAT, where the mode and coincidence bits
+    // are read only.
+    pub fn write_stat(&mut self, value: u8) {
+        if !self.is_lcd_enabled() {
+            self.memory.ram[0x41] = 0x80 | (value & 0x78) | (self.memory.ram[0x41] & 0x7);
+            return;
         }
 
-        if current_line == self.read_upper_ram(0xFF45) {
-            status = set(status, 2);
-            if test(status,  6) {
-                self.request_interrupt(1);
+        // On DMG every source is enabled for a cycle during the write, which
+        // requests an interrupt in HBlank, VBlank or on LY=LYC.
+        if !self.cgb_mode {
+            let status = self.memory.ram[0x41] | 0x58;
+            if self.stat_sources(status) && !self.ppu.stat_line {
+                self.request_interrupt(LCD_STAT);
             }
-        } else {
-            status = reset(status, 2);
         }
 
-        self.write(0xFF41, status);
+        self.memory.ram[0x41] = 0x80 | (value & 0x78) | (self.memory.ram[0x41] & 0x7);
+        self.update_stat_line();
     }
 
-    fn is_lcd_enabled(&self) -> bool {
-        return test(self.read_upper_ram(LCDC), 7);
+    pub fn write_lyc(&mut self, value: u8) {
+        self.memory.ram[0x45] = value;
+        if self.is_lcd_enabled() {
+            self.compare_ly();
+        }
     }
 
-    fn draw_scanline(&mut self, scanline: u8) {
-        let control = self.read_upper_ram(LCDC);
-
-        if self.cgb_mode || test(control, 0) {
-            self.render_tiles(control, scanline);
+    // write_lcdc handles writes to LCDC, turning the LCD on or off when bit 7
+    // changes.
+    pub fn write_lcdc(&mut self, value: u8) {
+        let enabled = self.is_lcd_enabled();
+        self.memory.ram[0x40] = value;
+        if enabled && !test(value, 7) {
+            self.disable_lcd();
+        } else if !enabled && test(value, 7) {
+            self.enable_lcd();
         }
+    }
 
-        if test(control, 1) {
-            self.render_sprite(control, scanline as i32);
-        }
+    // disable_lcd turns the LCD off. Games should only do this during VBlank,
+    // as doing it mid frame can damage the screen of a DMG. The hardware
+    // still turns off straight away, and so does the emulator. The screen is
+    // only blanked by end_frame, if the LCD is still off by then.
+    fn disable_lcd(&mut self) {
+        self.ppu.dot = 0;
+        self.ppu.line = 0;
+        self.ppu.mode = 0;
+        self.ppu.stat_line = false;
+        self.memory.ram[0x44] = 0;
+        self.memory.ram[0x41] &= !0x3;
     }
 
-    pub fn get_tile_settings(&self, lcd_control: u8, window_y: u8) -> TileSettings {
-        let mut tile_data = 0x8800_u16;
-        let mut unsigned = false;
-        let mut using_window = false;
+    // enable_lcd turns the LCD back on, starting from the first dot of line
+    // 0. The frame drawn next is left off the screen.
+    fn enable_lcd(&mut self) {
+        self.cleared = false;
+        self.ppu.dot = 0;
+        self.ppu.line = 0;
+        self.ppu.mode = 0;
+        self.ppu.lcd_starting = true;
+        self.ppu.skip_frame = true;
+        self.memory.ram[0x44] = 0;
+        self.compare_ly();
+    }
 
-        if test(lcd_control, 5) {
-            if window_y <= self.read_upper_ram(0xFF44) {
-                using_window = true;
-            }
+    // end_frame is called once a frame's worth of cycles has run. No frames
+    // are drawn while the LCD is off, so the screen is blanked here rather
+    // than as soon as it is turned off, which games often do for a moment
+    // during VBlank to load graphics.
+    pub fn end_frame(&mut self) {
+        if !self.is_lcd_enabled() {
+            self.clear_screen();
         }
+    }
 
-        if test(lcd_control, 4) {
-            tile_data = 0x8000;
-            unsigned = true;
-        }
+    fn is_lcd_enabled(&self) -> bool {
+        return test(self.read_upper_ram(LCDC), 7);
+    }
 
-        let mut background_memory = 0x9800_u16;
-        if test(lcd_control, if using_window {6} else {3}) {
-            background_memory = 0x9C00_u16;
+    // scan_oam_entry checks one OAM entry during mode 2, which takes two
+    // dots per entry. The first 10 sprites overlapping the line are kept,
+    // whether or not they are on screen horizontally.
+    fn scan_oam_entry(&mut self, line: u8, index: usize) {
+        // OAM reads 0xFF to the PPU during OAM DMA, so no sprites are found.
+        if self.ppu.line_sprites.len() == 10 || self.memory.dma.active {
+            return;
         }
 
-        return TileSettings {
-            using_window,
-            unsigned,
-            tile_data,
-            background_memory
+        let height = if test(self.memory.ram[0x40], 2) { 16 } else { 8 };
+        let entry = index * 4;
+        let y = self.memory.oam[entry];
+        let top = (y as i16) - 16;
+        if (line as i16) < top || (line as i16) >= top + height {
+            return;
         }
-    }
 
-    pub fn render_tiles(&mut self, lcd_control: u8, scanline: u8) {
-        let scroll_y = self.read_upper_ram(0xFF42);
-        let scroll_x = self.read_upper_ram(0xFF43);
-        let window_y = self.read_upper_ram(0xFF4A);
-        let window_x: u8 = self.read_upper_ram(0xFF4B).wrapping_sub(7);
-
-        let settings = self.get_tile_settings(lcd_control, window_y);
-        let y_pos = if !settings.using_window {scroll_y.wrapping_add(scanline)} else {scanline.wrapping_sub(window_y)};
-
-        let tile_row = ((y_pos/8) as u16).wrapping_mul(32_u16);
+        self.ppu.line_sprites.push(Sprite {
+            index: index as u8,
+            y,
+            x: self.memory.oam[entry + 1],
+            tile: self.memory.oam[entry + 2],
+            attributes: self.memory.oam[entry + 3],
+            fetched: false,
+        });
+    }
 
-        let palette = self.read_upper_ram(0xFF47);
+    fn start_pixel_transfer(&mut self) {
+        self.ppu.bg_fifo.clear();
+        self.ppu.sprite_fifo.clear();
+        self.ppu.fetcher.reset(false);
+        self.ppu.lx = 0;
+        self.ppu.discard = self.memory.ram[0x43] & 0x7;
+        self.ppu.first_fetch = true;
+        self.ppu.sprite_fetch = None;
+        self.ppu.window_active = false;
+        self.ppu.window_drawn = false;
+
+        if self.ppu.window_next_line {
+            self.ppu.window_next_line = false;
+            if test(self.memory.ram[0x40], 5) {
+                self.start_window(0);
+            }
+        }
+    }
 
-        self.tile_scanline = [0; SCREEN_WIDTH as usize];
-        for pixel in 0_u8..SCREEN_WIDTH {
-            let mut x_pos: u8 = if settings.using_window && (pixel) >= (window_x) {
-                (pixel).wrapping_sub(window_x )
-            } else {
-                (pixel).wrapping_add(scroll_x)
-            };
+    // start_window switches the fetcher over to the window, dropping the
+    // given number of its first pixels.
+    fn start_window(&mut self, discard: u8) {
+        self.ppu.window_active = true;
+        self.ppu.window_drawn = true;
+        self.ppu.bg_fifo.clear();
+        self.ppu.fetcher.reset(true);
+        self.ppu.discard = discard;
+    }
 
-            let tile_col = (x_pos/8) as u16;
+    // stop_window switches the fetcher back to the background when the
+    // window is disabled part way through a line.
+    fn stop_window(&mut self) {
+        self.ppu.window_active = false;
+        let scroll_x = self.memory.ram[0x43];
+        let position = (scroll_x & 0x7) as usize + self.ppu.lx as usize + self.ppu.bg_fifo.len();
+        self.ppu.fetcher.reset(false);
+        self.ppu.fetcher.x = (position / 8) as u8;
+    }
 
-            let tile_address = settings.background_memory.wrapping_add(tile_row).wrapping_add(tile_col);
+    // step_pixel_transfer runs one dot of mode 3. The background fetcher
+    // feeds the background FIFO, which shifts out one pixel a dot. Starting
+    // the window restarts the fetcher and reaching a sprite pauses shifting
+    // while its pixels are fetched, which is what makes mode 3 longer.
+    fn step_pixel_transfer(&mut self, line: u8) {
+        let control = self.memory.ram[0x40];
 
-            let mut tile_location = settings.tile_data;
-            let tile_num: i16;
-            if settings.unsigned {
-                tile_num = self.memory.vram[tile_address.wrapping_sub(0x8000) as usize] as i16;
-                tile_location = tile_location.wrapping_add(tile_num.wrapping_mul(16) as u16);
+        if let Some((sprite, dots)) = self.ppu.sprite_fetch {
+            if dots > 1 {
+                self.ppu.sprite_fetch = Some((sprite, dots - 1));
             } else {
-                tile_num = (self.memory.vram[tile_address.wrapping_sub(0x8000) as usize] as i8)as i16;
-                tile_location = (tile_location as i32).wrapping_add(tile_num.wrapping_add(128).wrapping_mul(16) as i32) as u16;
+                self.ppu.sprite_fetch = None;
+                self.fetch_sprite(sprite, line, control);
             }
+            return;
+        }
 
-            let mut bank_offset = 0x8000_u16;
-            // Attributes used in CGB mode TODO: check in CGB mode
-            //
-            //    Bit 0-2  Background Palette number  (BGP0-7)
-            //    Bit 3    Tile VRAM Bank number      (0=Bank 0, 1=Bank 1)
-            //    Bit 5    Horizontal Flip            (0=Normal, 1=Mirror horizontally)
-            //    Bit 6    Vertical Flip              (0=Normal, 1=Mirror vertically)
-            //    Bit 7    BG-to-OAM Priority         (0=Use OAM priority bit, 1=BG Priority)
-            let tile_attr = self.memory.vram[tile_address.wrapping_sub(0x6000) as usize];
-            if self.cgb_mode && test(tile_attr, 3) {
-                bank_offset = 0x6000_u16;
-            }
-            let priority = test(tile_attr, 7);
-            let line: u8;
-            if self.cgb_mode && test(tile_attr, 6) {
-                line = ((7_u8.wrapping_sub(y_pos)) % 8).wrapping_mul(2);
-            } else {
-                line = (y_pos % 8).wrapping_mul(2);
+        if self.ppu.window_active && !test(control, 5) {
+            self.stop_window();
+        } else if self.window_triggered(control) {
+            let window_x = self.memory.ram[0x4B];
+            if window_x == 166 {
+                // WX at 166 matches on the last pixel, even when the window
+                // is already being drawn, and starts it on the next line.
+                self.ppu.window_next_line = true;
+            } else if !self.ppu.window_active {
+                // A window starting left of the screen has its first pixels
+                // cut off. With WX at 0 the amount follows SCX instead.
+                let discard = match window_x {
+                    0 => self.memory.ram[0x43] & 0x7,
+                    1..=6 => 7 - window_x,
+                    _ => 0,
+                };
+                self.start_window(discard);
             }
+        }
 
-            let data_1 = self.memory.vram[tile_location.wrapping_add(line as u16).wrapping_sub(bank_offset) as usize];
-            let data_2 = self.memory.vram[tile_location.wrapping_add(line as u16).wrapping_add(1).wrapping_sub(bank_offset) as usize];
-
-            if self.cgb_mode && test(tile_attr, 5) {
-                x_pos = 7_u8.wrapping_sub(x_pos);
+        if test(control, 1) {
+            if let Some(sprite) = self.next_sprite() {
+                // The sprite fetch waits for the background fetcher to have
+                // the tile it is working on ready.
+                if self.ppu.fetcher.step == FetchStep::Push {
+                    self.ppu.sprite_fetch = Some((sprite, SPRITE_FETCH_DOTS));
+                } else {
+                    self.step_fetcher(line, control);
+                }
+                return;
             }
-            let color_bit = (((x_pos%8).wrapping_sub(7) as i8) * -1) as u8;
-            let color_num = (val(data_2, color_bit) << 1) | val(data_1, color_bit);
-            self.set_tile_pixel(pixel, scanline, tile_attr, color_num, palette, priority);
         }
+
+        self.step_fetcher(line, control);
+        self.shift_pixel(line, control);
     }
 
-    fn set_tile_pixel(&mut self, x: u8, y: u8, tile_attr: u8, color_num: u8, palette: u8, priority: bool) {
-        if self.cgb_mode {
-            let cgb_palette = tile_attr & 0x7;
-            let color = self.bg_palette.get(cgb_palette, color_num);
-            self.set_pixel(x, y, color, true);
-            self.bg_priority[x as usize][y as usize] = priority
-        } else {
-            let color = self.get_color(color_num, palette);
-            self.set_pixel(x,y, color, true);
+    // window_triggered returns whether the window starts at the current
+    // pixel. Once it has started it is drawn until the end of the line.
+    fn window_triggered(&self, control: u8) -> bool {
+        if !test(control, 5) || (!self.cgb_mode && !test(control, 0)) || !self.ppu.window_y_triggered {
+            return false;
         }
-
-        self.tile_scanline[x as usize] = color_num;
+        let window_x = self.memory.ram[0x4B];
+        if window_x > 166 || self.ppu.window_next_line {
+            return false;
+        }
+        return (self.ppu.lx as u16) + 7 >= window_x as u16;
     }
 
-    fn get_color(&self, color_num: u8, palette: u8) -> ColorPixel {
-        let hi = color_num << 1 | 1;
-        let lo = color_num << 1;
-        let col = val(palette, hi) << 1 | val(palette, lo);
-        return self.get_palette_color(col as usize);
+    // next_sprite returns the sprite not yet fetched that starts at the
+    // current pixel. Several sprites can start at the first pixel of a line,
+    // in which case the leftmost one is fetched first, then the one earliest
+    // in OAM.
+    fn next_sprite(&self) -> Option<usize> {
+        let lx = self.ppu.lx as u16;
+        let mut next: Option<usize> = None;
+        for (i, sprite) in self.ppu.line_sprites.iter().enumerate() {
+            if sprite.fetched || (sprite.x as u16) > lx + 8 {
+                continue;
+            }
+            match next {
+                Some(n) if self.ppu.line_sprites[n].x <= sprite.x => {}
+                _ => next = Some(i),
+            }
+        }
+        return next;
     }
 
-    fn render_sprite(&mut self, lcd_control: u8, scanline: i32) {
-        let y_size = if test(lcd_control, 2) {16_i32} else {8_i32};
-
-        let palette_1 = self.read_upper_ram(0xFF48);
-        let palette_2 = self.read_upper_ram(0xFF49);
-
-        let mut minx: [i32; SCREEN_WIDTH as usize ] = [0_i32; SCREEN_WIDTH as usize];
-        let mut line_sprites = 0;
-        for sprite in 0_u16..40_u16 {
-            let index = sprite.wrapping_mul(4);
-            let y_pos = (self.read(0xFE00_u16.wrapping_add(index)) as i32).wrapping_sub(16);
-            if scanline < y_pos || scanline >= y_pos.wrapping_add(y_size) {
-                continue
+    fn step_fetcher(&mut self, line: u8, control: u8) {
+        match self.ppu.fetcher.step {
+            FetchStep::Tile => {
+                self.ppu.fetcher.dots += 1;
+                if self.ppu.fetcher.dots == 2 {
+                    self.fetch_tile_number(line, control);
+                    self.ppu.fetcher.dots = 0;
+                    self.ppu.fetcher.step = FetchStep::DataLow;
+                }
             }
-
-            if line_sprites >= 10 {
-                break;
+            FetchStep::DataLow => {
+                self.ppu.fetcher.dots += 1;
+                if self.ppu.fetcher.dots == 2 {
+                    let addr = self.tile_data_address(line, control);
+                    self.ppu.fetcher.low = self.memory.vram[addr];
+                    self.ppu.fetcher.dots = 0;
+                    self.ppu.fetcher.step = FetchStep::DataHigh;
+                }
             }
+            FetchStep::DataHigh => {
+                self.ppu.fetcher.dots += 1;
+                if self.ppu.fetcher.dots == 2 {
+                    let addr = self.tile_data_address(line, control);
+                    self.ppu.fetcher.high = self.memory.vram[addr + 1];
+                    self.ppu.fetcher.dots = 0;
+                    self.ppu.fetcher.step = FetchStep::Push;
+                }
+            }
+            FetchStep::Push => {
+                if !self.ppu.bg_fifo.is_empty() {
+                    return;
+                }
+                // The first tile fetched on each line is thrown away.
+                if self.ppu.first_fetch {
+                    self.ppu.first_fetch = false;
+                } else {
+                    self.push_tile_row();
+                    self.ppu.fetcher.x = self.ppu.fetcher.x.wrapping_add(1);
+                }
+                self.ppu.fetcher.step = FetchStep::Tile;
+            }
+        }
+    }
 
-            line_sprites += 1;
-            let x_pos = (self.read(0xFE00_u16.wrapping_add(index).wrapping_add(1)) as i32).wrapping_sub(8);
-            let tile_location = self.read(0xFE00_u16.wrapping_add(index).wrapping_add(2)) as u16;
-            let attributes = self.read(0xFE00_u16.wrapping_add(index).wrapping_add(3));
+    // fetcher_y returns the line within the background or window map that
+    // the fetcher is working on.
+    fn fetcher_y(&self, line: u8) -> u8 {
+        if self.ppu.fetcher.window {
+            return self.ppu.window_line;
+        }
+        return line.wrapping_add(self.memory.ram[0x42]);
+    }
 
-            let y_filp = test(attributes, 6);
-            let x_flip = test(attributes, 5);
-            let priority = !test(attributes, 7);
+    fn fetch_tile_number(&mut self, line: u8, control: u8) {
+        let y = self.fetcher_y(line) as usize;
+        let (map, x) = if self.ppu.fetcher.window {
+            (if test(control, 6) { 0x1C00 } else { 0x1800 }, self.ppu.fetcher.x)
+        } else {
+            let scroll_x = self.memory.ram[0x43];
+            (if test(control, 3) { 0x1C00 } else { 0x1800 }, (scroll_x >> 3).wrapping_add(self.ppu.fetcher.x))
+        };
+
+        let addr = map + (y / 8) * 32 + ((x & 0x1F) as usize);
+        self.ppu.fetcher.tile = self.memory.vram[addr];
+        // Attributes used in CGB mode, stored in VRAM bank 1
+        //
+        //    Bit 0-2  Background Palette number  (BGP0-7)
+        //    Bit 3    Tile VRAM Bank number      (0=Bank 0, 1=Bank 1)
+        //    Bit 5    Horizontal Flip            (0=Normal, 1=Mirror horizontally)
+        //    Bit 6    Vertical Flip              (0=Normal, 1=Mirror vertically)
+        //    Bit 7    BG-to-OAM Priority         (0=Use OAM priority bit, 1=BG Priority)
+        self.ppu.fetcher.attributes = if self.cgb_mode { self.memory.vram[0x2000 + addr] } else { 0 };
+    }
 
+    fn tile_data_address(&self, line: u8, control: u8) -> usize {
+        let tile = self.ppu.fetcher.tile;
+        let attributes = self.ppu.fetcher.attributes;
 
-            let bank = if self.cgb_mode && test(attributes, 3) {1_u16} else {0_u16};
+        let tile_location = if test(control, 4) {
+            (tile as usize) * 16
+        } else {
+            (0x1000 + (tile as i8 as isize) * 16) as usize
+        };
 
-            let mut line = scanline.wrapping_sub(y_pos);
-            if y_filp {
-                line = y_size.wrapping_sub(line).wrapping_sub(1);
-            }
+        let mut row = (self.fetcher_y(line) % 8) as usize;
+        if test(attributes, 6) {
+            row = 7 - row;
+        }
+        let bank_offset = if test(attributes, 3) { 0x2000 } else { 0 };
+        return bank_offset + tile_location + row * 2;
+    }
 
-            let addr = (tile_location.wrapping_mul(16).wrapping_add(line.wrapping_mul(2) as u16)).wrapping_add(bank.wrapping_mul(0x2000));
-            let data_1 = self.memory.vram[addr as usize];
-            let data_2 = self.memory.vram[(addr.wrapping_add(1)) as usize];
+    fn push_tile_row(&mut self) {
+        let attributes = self.ppu.fetcher.attributes;
+        for pixel in 0_u8..8 {
+            let bit = if test(attributes, 5) { pixel } else { 7 - pixel };
+            let color = (val(self.ppu.fetcher.high, bit) << 1) | val(self.ppu.fetcher.low, bit);
+            self.ppu.bg_fifo.push_back(FifoPixel {
+                color,
+                palette: attributes & 0x7,
+                priority: test(attributes, 7),
+                sprite: 0,
+            });
+        }
+    }
 
-            for tile_pixel in 0_u8..8_u8{
-                let pixel = (x_pos as i16).wrapping_add(7_u8.wrapping_sub(tile_pixel) as i16);
-                if pixel < 0 || pixel >= SCREEN_WIDTH as i16{
-                    continue
-                }
+    fn fetch_sprite(&mut self, index: usize, line: u8, control: u8) {
+        self.ppu.line_sprites[index].fetched = true;
+        let sprite = self.ppu.line_sprites[index];
+        let attributes = sprite.attributes;
+        let (height, tile) = if test(control, 2) {
+            // In 8x16 mode the top tile is the even one and bit 0 of the
+            // tile index is ignored.
+            (16, sprite.tile & 0xFE)
+        } else {
+            (8, sprite.tile)
+        };
 
-                if minx[pixel as usize] != 0 && (self.cgb_mode || minx[pixel as usize] <= x_pos+SPRITE_PRIORITY_OFFSET as i32) {
-                    continue
+        let mut row = (line as i16) - (sprite.y as i16 - 16);
+        if test(attributes, 6) {
+            row = height - 1 - row;
+        }
+        // The sprite was picked by the OAM scan with the height LCDC had then.
+        // If it has shrunk since, only the low bits of the row are used, as
+        // on hardware.
+        let row = (row & (height - 1)) as usize;
+        let bank_offset = if self.cgb_mode && test(attributes, 3) { 0x2000 } else { 0 };
+        let addr = bank_offset + (tile as usize) * 16 + row * 2;
+        let low = self.memory.vram[addr];
+        let high = self.memory.vram[addr + 1];
+
+        // Pixels of sprites partly off the left edge of the screen are dropped.
+        let skip = ((self.ppu.lx as u16) + 8).saturating_sub(sprite.x as u16) as u8;
+        for pixel in skip..8 {
+            let bit = if test(attributes, 5) { pixel } else { 7 - pixel };
+            let sprite_pixel = FifoPixel {
+                color: (val(high, bit) << 1) | val(low, bit),
+                palette: if self.cgb_mode { attributes & 0x7 } else { val(attributes, 4) },
+                priority: test(attributes, 7),
+                sprite: sprite.index,
+            };
+
+            // On DMG sprites fetched earlier, which are the ones further to
+            // the left, keep their pixels unless transparent. On CGB the
+            // sprite earliest in OAM wins instead.
+            let position = (pixel - skip) as usize;
+            if position < self.ppu.sprite_fifo.len() {
+                let current = self.ppu.sprite_fifo[position];
+                let replace = current.color == 0
+                    || (self.cgb_mode && sprite_pixel.color != 0 && sprite.index < current.sprite);
+                if replace {
+                    self.ppu.sprite_fifo[position] = sprite_pixel;
                 }
+            } else {
+                self.ppu.sprite_fifo.push_back(sprite_pixel);
+            }
+        }
+    }
 
-                let color_bit = if x_flip {
-                    ((tile_pixel.wrapping_sub(7) as i8) * -1) as u8
-                } else {
-                    tile_pixel
-                };
+    fn shift_pixel(&mut self, line: u8, control: u8) {
+        let bg = match self.ppu.bg_fifo.pop_front() {
+            Some(pixel) => pixel,
+            None => return,
+        };
+        if self.ppu.discard > 0 {
+            self.ppu.discard -= 1;
+            return;
+        }
 
-                let color_num = (val(data_2, color_bit) << 1) | val(data_1, color_bit);
+        let sprite = self.ppu.sprite_fifo.pop_front();
+        let color = self.mix_pixel(bg, sprite, control);
+        self.working_screen[self.ppu.lx as usize][line as usize] = color;
+        self.ppu.lx += 1;
+    }
 
-                // Colour 0 is transparent for sprites
-                if color_num == 0 {
-                    continue
-                }
+    // mix_pixel picks between the background and sprite pixel shifted out
+    // together and applies the palette registers as they are right now.
+    fn mix_pixel(&self, bg: FifoPixel, sprite: Option<FifoPixel>, control: u8) -> ColorPixel {
+        let sprite = sprite.filter(|pixel| pixel.color != 0 && test(control, 1));
 
-                if self.cgb_mode {
-                    let cgb_palette = attributes & 0x7;
-                    let color = self.sprite_palette.get(cgb_palette, color_num);
-                    self.set_pixel(pixel as u8, scanline as u8, color, priority);
-                } else {
-                    let palette = if test(attributes, 4) {palette_2} else {palette_1};
-                    let color = self.get_color(color_num, palette);
-                    self.set_pixel(pixel as u8, scanline as u8, color, priority)
+        if self.cgb_mode {
+            // On CGB clearing LCDC bit 0 gives sprites priority over the
+            // background and window whatever their attributes say.
+            if let Some(sprite) = sprite {
+                if !test(control, 0) || bg.color == 0 || (!bg.priority && !sprite.priority) {
+                    return self.sprite_palette.get(sprite.palette, sprite.color);
                 }
-
-                minx[pixel as usize] = x_pos + SPRITE_PRIORITY_OFFSET as i32;
             }
+            return self.bg_palette.get(bg.palette, bg.color);
         }
 
+        // On DMG clearing LCDC bit 0 blanks the background and window.
+        let bg_color = if test(control, 0) { bg.color } else { 0 };
+        if let Some(sprite) = sprite {
+            if !sprite.priority || bg_color == 0 {
+                let palette = self.memory.ram[0x48 + sprite.palette as usize];
+                return self.get_color(sprite.color, palette);
+            }
+        }
+        return self.get_color(bg_color, self.memory.ram[0x47]);
     }
 
-    fn set_pixel(&mut self, x: u8, y: u8, color: ColorPixel, priority: bool) {
-        // If priority is false then sprite pixel is only set if tile colour is 0
-        if (priority && !self.bg_priority[x as usize][y as usize]) || self.tile_scanline[x as usize] == 0 {
-            self.working_screen[x as usize][y as usize] = color;
-        }
+    fn get_color(&self, color_num: u8, palette: u8) -> ColorPixel {
+        let hi = color_num << 1 | 1;
+        let lo = color_num << 1;
+        let col = val(palette, hi) << 1 | val(palette, lo);
+        return self.get_palette_color(col as usize);
     }
 
     pub fn clear_screen(&mut self) {
@@ -346,4 +594,4 @@ impl Gameboy {
         self.cleared = true;
 
     }
-}
\ No newline at end of file
+}
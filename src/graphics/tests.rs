@@ -0,0 +1,100 @@
+use crate::cart::controller::Cart;
+use crate::gameboy::Gameboy;
+
+const LINE_DOTS: usize = 456;
+
+fn gameboy() -> Gameboy {
+    return Gameboy::from_cart(Cart::from_rom(vec![0; 0x8000], "").unwrap());
+}
+
+// same_color returns whether the pixels drawn at x, y and x2, y are the same
+// color.
+fn same_color(gb: &Gameboy, x: usize, x2: usize, y: usize) -> bool {
+    let (a, b) = (gb.working_screen[x][y], gb.working_screen[x2][y]);
+    return a.r == b.r && a.g == b.g && a.b == b.b;
+}
+
+#[test]
+fn sprite_shrinking_after_oam_scan_masks_row() {
+    let mut gb = gameboy();
+    // A Y flipped sprite at the top left, 12 rows into which line 12 is.
+    gb.memory.oam[0..4].copy_from_slice(&[16, 8, 0, 0x40]);
+    // Row 3 of tile 0 is the only one with pixels set.
+    gb.memory.vram[6] = 0xFF;
+    gb.memory.ram[0x40] = 0x97;
+
+    // Switch to 8x8 sprites after the OAM scan of line 12, which found the
+    // sprite while it was 16 rows high. Row 7 - 12 is masked to row 3.
+    gb.update_graphics(12 * LINE_DOTS + 81);
+    gb.memory.ram[0x40] = 0x93;
+    gb.update_graphics(LINE_DOTS);
+    assert!(!same_color(&gb, 0, 20, 12));
+    assert!(same_color(&gb, 8, 20, 12));
+}
+
+#[test]
+fn window_turned_off_mid_line_still_advances_window_line() {
+    let mut gb = gameboy();
+    gb.memory.ram[0x4A] = 0;
+    gb.memory.ram[0x4B] = 7;
+    gb.memory.ram[0x40] = 0xB1;
+
+    // Turn the window off half way through line 0.
+    gb.update_graphics(80 + 100);
+    gb.memory.ram[0x40] = 0x91;
+    gb.update_graphics(LINE_DOTS - 180);
+    assert_eq!(gb.ppu.window_line, 1);
+
+    // Line 1 has no window, so it does not advance.
+    gb.update_graphics(LINE_DOTS);
+    assert_eq!(gb.ppu.window_line, 1);
+}
+
+#[test]
+fn screen_is_blanked_once_lcd_is_off_at_end_of_frame() {
+    let mut gb = gameboy();
+    gb.rendered_screen[0][0].r = 0;
+
+    // Turning the LCD off and back on within a frame keeps the screen.
+    gb.write(0xFF40, 0x11);
+    gb.write(0xFF40, 0x91);
+    gb.update();
+    assert_eq!(gb.rendered_screen[0][0].r, 0);
+    assert!(gb.frame_skipped);
+
+    gb.write(0xFF40, 0x11);
+    assert_eq!(gb.rendered_screen[0][0].r, 0);
+    gb.update();
+    assert_eq!(gb.rendered_screen[0][0].r, 255);
+}
+
+// oam_scanning returns a DMG Gameboy part way through the OAM scan of line
+// 1, with every byte of OAM holding its own index and SP pointing into OAM.
+fn oam_scanning() -> Gameboy {
+    let mut gb = gameboy();
+    for i in 0..0xA0 {
+        gb.memory.oam[i] = i as u8;
+    }
+    gb.update_graphics(LINE_DOTS + 20);
+    gb.cpu.sp.set_full(0xFE40);
+    return gb;
+}
+
+#[test]
+fn interrupt_dispatch_corrupts_oam_like_push() {
+    // PUSH BC
+    let mut push = oam_scanning();
+    push.write(0xC000, 0xC5);
+    push.cpu.pc = 0xC000;
+    push.execute_next_opcode();
+
+    // Dispatching an interrupt spends one more cycle before pushing PC, the
+    // same time as the opcode fetch of the PUSH.
+    let mut dispatch = oam_scanning();
+    dispatch.interrupts.enable();
+    dispatch.interrupts.enabled = 0x1;
+    dispatch.do_interrupts();
+
+    assert_ne!(push.memory.oam[..0xA0], oam_scanning().memory.oam[..0xA0]);
+    assert_eq!(dispatch.memory.oam[..0xA0], push.memory.oam[..0xA0]);
+}
@@ -0,0 +1,77 @@
+use std::fs;
+use crate::gameboy::Gameboy;
+
+// run_headless runs the emulator without a window, for at most the given
+// number of frames. Whatever the ROM sends over the serial port is captured
+// and written to serial_out, a file path or "-" for stdout. The run stops
+// early once the output reports "Passed" or "Failed", the way blargg's test
+// ROMs do, and returns whether it passed.
+pub fn run_headless(gb: &mut Gameboy, frames: usize, serial_out: Option<&str>) -> bool {
+    let output = gb.capture_serial();
+    let mut text = String::new();
+    for _ in 0..frames {
+        gb.update();
+        text = output.text();
+        if text.contains("Passed") || text.contains("Failed") {
+            break;
+        }
+    }
+
+    match serial_out {
+        Some("-") => print!("{}", text),
+        Some(path) => {
+            if let Err(e) = fs::write(path, &text) {
+                println!("Unable to write serial output to {}: {}", path, e);
+            }
+        }
+        None => {}
+    }
+    return text.contains("Passed") && !text.contains("Failed");
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cart::controller::Cart;
+    use crate::gameboy::Gameboy;
+    use crate::headless::run_headless;
+
+    // printing returns a Gameboy running a ROM that sends text over the
+    // serial port a byte at a time, then loops forever.
+    fn printing(text: &str) -> Gameboy {
+        let mut rom = vec![0; 0x8000];
+        let program = [
+            0x21, 0x50, 0x01, // LD HL,0x0150
+            0x2A,             // loop: LD A,(HL+)
+            0xB7,             // OR A
+            0x28, 0x0E,       // JR Z,done
+            0xE0, 0x01,       // LDH (SB),A
+            0x3E, 0x81,       // LD A,0x81
+            0xE0, 0x02,       // LDH (SC),A
+            0xF0, 0x02,       // wait: LDH A,(SC)
+            0xCB, 0x7F,       // BIT 7,A
+            0x20, 0xFA,       // JR NZ,wait
+            0x18, 0xEE,       // JR loop
+            0x18, 0xFE,       // done: JR done
+        ];
+        rom[0x100..0x100 + program.len()].copy_from_slice(&program);
+        rom[0x150..0x150 + text.len()].copy_from_slice(text.as_bytes());
+        return Gameboy::from_cart(Cart::from_rom(rom, "").unwrap());
+    }
+
+    #[test]
+    fn captures_serial_output() {
+        let mut gb = printing("cpu_instrs\nPassed\n");
+        let path = std::env::temp_dir().join(format!("gameboy-serial-{}.txt", std::process::id()));
+        let passed = run_headless(&mut gb, 60, path.to_str());
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(passed);
+        assert_eq!(text, "cpu_instrs\nPassed\n");
+    }
+
+    #[test]
+    fn reports_failures() {
+        let mut gb = printing("Failed #3");
+        assert!(!run_headless(&mut gb, 60, None));
+    }
+}
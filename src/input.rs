@@ -1,6 +1,7 @@
-pub(crate) type Button = u8;
+pub type Button = u8;
 use crate::bit_functions::{reset, set, test};
 use crate::gameboy::Gameboy;
+use crate::interrupts::JOYPAD;
 
 pub const A: Button = 0;
 pub const B: Button = 1;
@@ -30,7 +31,12 @@ impl Input {
 impl Gameboy {
     pub fn press_button(&mut self, button: Button) {
         self.memory.input.mask = reset(self.memory.input.mask, button);
-        self.request_interrupt(4);
+        self.request_interrupt(JOYPAD);
+
+        // STOP mode is left once a selected joypad line goes low.
+        if self.cpu.stopped && self.read_upper_ram(0xFF00) & 0xF != 0xF {
+            self.cpu.stopped = false;
+        }
     }
 
     pub fn release_button(&mut self, button: Button) {
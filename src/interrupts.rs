@@ -0,0 +1,141 @@
+use crate::bit_functions::{reset, set};
+use crate::gameboy::Gameboy;
+
+pub const VBLANK: u8 = 0;
+pub const LCD_STAT: u8 = 1;
+pub const TIMER: u8 = 2;
+pub const SERIAL: u8 = 3;
+pub const JOYPAD: u8 = 4;
+
+// InterruptController holds the interrupt master enable (IME) along with
+// the IE (0xFFFF) and IF (0xFF0F) registers.
+pub struct InterruptController {
+    // Interrupt master enable.
+    pub ime: bool,
+    // The number of instruction boundaries left before a pending EI takes
+    // effect. EI only enables interrupts after the instruction following it.
+    ime_delay: u8,
+    // IE, the interrupts that are allowed to be serviced.
+    pub enabled: u8,
+    // IF, the interrupts that have been requested.
+    pub requested: u8,
+}
+
+impl InterruptController {
+    pub fn new() -> InterruptController {
+        return InterruptController {
+            ime: false,
+            ime_delay: 0,
+            enabled: 0,
+            requested: 0x1,
+        };
+    }
+
+    pub fn request(&mut self, interrupt: u8) {
+        self.requested = set(self.requested, interrupt);
+    }
+
+    // pending returns the interrupts that are both requested and enabled.
+    pub fn pending(&self) -> u8 {
+        return self.requested & self.enabled & 0x1F;
+    }
+
+    // enable_delayed schedules IME to be set after the next instruction, as
+    // done by EI.
+    pub fn enable_delayed(&mut self) {
+        if !self.ime {
+            self.ime_delay = 2;
+        }
+    }
+
+    // enable sets IME immediately, as done by RETI.
+    pub fn enable(&mut self) {
+        self.ime = true;
+        self.ime_delay = 0;
+    }
+
+    pub fn disable(&mut self) {
+        self.ime = false;
+        self.ime_delay = 0;
+    }
+
+    // step is called at every instruction boundary to apply a delayed EI.
+    pub fn step(&mut self) {
+        if self.ime_delay > 0 {
+            self.ime_delay -= 1;
+            if self.ime_delay == 0 {
+                self.ime = true;
+            }
+        }
+    }
+
+    pub fn read_flags(&self) -> u8 {
+        return self.requested | 0xE0;
+    }
+
+    pub fn write_flags(&mut self, value: u8) {
+        self.requested = value & 0x1F;
+    }
+}
+
+impl Gameboy {
+    pub fn request_interrupt(&mut self, interrupt: u8) {
+        self.interrupts.request(interrupt);
+    }
+
+    // do_interrupts is run after every instruction. It wakes the CPU from
+    // HALT and dispatches the highest priority pending interrupt, returning
+    // the number of cycles spent doing so.
+    pub fn do_interrupts(&mut self) -> usize {
+        self.interrupts.step();
+
+        if self.interrupts.pending() == 0 {
+            return 0;
+        }
+
+        // A pending interrupt always ends HALT, but is only serviced if
+        // interrupts are enabled.
+        self.cpu.halted = false;
+        if !self.interrupts.ime {
+            return 0;
+        }
+
+        self.cpu.clock.t = 0;
+        self.service_interrupt();
+        return self.cpu.clock.t;
+    }
+
+    // service_interrupt pushes the program counter and jumps to the vector
+    // of the highest priority pending interrupt, taking 5 machine cycles.
+    fn service_interrupt(&mut self) {
+        self.interrupts.disable();
+
+        self.tick();
+        // SP is decremented in the second cycle, which can corrupt OAM just
+        // like the internal cycle of a push.
+        self.increment_cycle(self.cpu.sp.full());
+
+        let pc = self.cpu.pc;
+        let mut sp = self.cpu.sp.full().wrapping_sub(1);
+        self.write_cycle(sp, (pc >> 8) as u8);
+
+        // The interrupt is chosen after the upper byte of the program counter
+        // has been pushed. If that push overwrote IE and nothing is pending
+        // anymore the dispatch is cancelled and execution continues at 0x0000.
+        let pending = self.interrupts.pending();
+
+        sp = sp.wrapping_sub(1);
+        self.write_cycle(sp, (pc & 0xFF) as u8);
+        self.cpu.sp.set_full(sp);
+        self.tick();
+
+        if pending == 0 {
+            self.cpu.pc = 0x0000;
+            return;
+        }
+
+        let interrupt = pending.trailing_zeros() as u8;
+        self.interrupts.requested = reset(self.interrupts.requested, interrupt);
+        self.cpu.pc = 0x40 + (interrupt as u16) * 8;
+    }
+}
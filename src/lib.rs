@@ -0,0 +1,15 @@
+// The emulator itself, shared by the windowed frontend in main.rs and the
+// test ROM suite in tests/.
+pub mod bit_functions;
+pub mod cart;
+pub mod cheats;
+pub mod cpu;
+pub mod debugger;
+pub mod gameboy;
+pub mod input;
+pub mod interrupts;
+pub mod graphics;
+pub mod headless;
+pub mod memory;
+pub mod serial;
+// pub mod sound;
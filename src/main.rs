@@ -1,18 +1,12 @@
-mod bit_functions;
-mod cart;
-mod cpu;
-mod gameboy;
-mod input;
-mod graphics;
-mod memory;
 mod pixels_helper;
-// mod sound;
 
 extern crate nfd;
-use crate::gameboy::Gameboy;
+use gameboy_emulator::gameboy::Gameboy;
+use gameboy_emulator::cart::archive::{is_archive, rom_entries};
+use gameboy_emulator::cart::controller::Cart;
 use crate::pixels_helper::{create_window, get_keymap};
-use crate::input::Button;
-use crate::graphics::{SCREEN_WIDTH, SCREEN_HEIGHT};
+use gameboy_emulator::input::Button;
+use gameboy_emulator::graphics::{SCREEN_WIDTH, SCREEN_HEIGHT};
 use pixels::{Pixels, SurfaceTexture};
 use winit::event::{Event, VirtualKeyCode};
 use winit::event_loop::{ControlFlow, EventLoop};
@@ -20,12 +14,38 @@ use winit_input_helper::WinitInputHelper;
 use nfd::Response;
 use std::process::exit;
 use std::collections::HashMap;
+use gameboy_emulator::serial::printer::Printer;
+use gameboy_emulator::serial::tcp::TcpLink;
+use std::path::{Path, PathBuf};
+use gameboy_emulator::headless::run_headless;
+use gameboy_emulator::cheats::{load_cheats, save_cheats};
+use gameboy_emulator::debugger::console::Console;
+use std::io::BufRead;
+use std::sync::mpsc::{channel, Receiver};
 
-fn main() -> () {
+// HEADLESS_FRAMES is how long --headless runs for when --frames isn't given,
+// about a minute of emulated time.
+const HEADLESS_FRAMES: usize = 3600;
+
+// CHEAT_KEYS toggle the cheats in the order they are listed in the cheat file.
+const CHEAT_KEYS: [VirtualKeyCode; 12] = [
+    VirtualKeyCode::F1, VirtualKeyCode::F2, VirtualKeyCode::F3, VirtualKeyCode::F4,
+    VirtualKeyCode::F5, VirtualKeyCode::F6, VirtualKeyCode::F7, VirtualKeyCode::F8,
+    VirtualKeyCode::F9, VirtualKeyCode::F10, VirtualKeyCode::F11, VirtualKeyCode::F12,
+];
+
+// arg_value returns the value following the given flag on the command line.
+fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    let index = args.iter().position(|arg| arg == name)?;
+    return args.get(index + 1).map(|value| value.as_str());
+}
+
+// select_rom asks for the ROM to load with a file dialog.
+fn select_rom() -> String {
     let mut correct_file: bool = false;
     let mut file = String::new();
     while !correct_file {
-        let result = nfd::dialog().filter("gb").filter("gbc").open().unwrap_or_else(|e| {
+        let result = nfd::dialog().filter("gb,gbc,zip,gz").open().unwrap_or_else(|e| {
             panic!(e);
         });
         match result {
@@ -40,10 +60,134 @@ fn main() -> () {
             }
         }
     }
+    return file;
+}
+
+// select_entry picks the ROM to load from an archive holding more than one,
+// either the one given with --entry or one the user chooses.
+fn select_entry(file: &str, args: &[String]) -> Option<String> {
+    if let Some(entry) = arg_value(args, "--entry") {
+        return Some(entry.to_string());
+    }
+    if !is_archive(Path::new(file)) {
+        return None;
+    }
+    let entries = rom_entries(Path::new(file)).unwrap_or_default();
+    if entries.len() < 2 {
+        return None;
+    }
+    for (i, entry) in entries.iter().enumerate() {
+        println!("{}: {}", i + 1, entry);
+    }
+    println!("Which ROM should be loaded? (default 1)");
+    let mut line = String::new();
+    let _ = std::io::stdin().read_line(&mut line);
+    let choice = line.trim().parse::<usize>().unwrap_or(1);
+    return entries.into_iter().nth(choice.max(1) - 1);
+}
+
+// link_cable sets up a link cable to another emulator if asked to with
+// --link-listen <address> or --link-connect <address>.
+fn link_cable(args: &[String]) -> Option<TcpLink> {
+    let link = if let Some(address) = arg_value(args, "--link-listen") {
+        println!("Listening for a link cable connection on {}", address);
+        TcpLink::listen(address)
+    } else if let Some(address) = arg_value(args, "--link-connect") {
+        TcpLink::connect(address)
+    } else {
+        return None;
+    };
+
+    return match link {
+        Ok(link) => Some(link),
+        Err(e) => {
+            println!("Unable to connect link cable: {}", e);
+            None
+        }
+    };
+}
+
+// console_input reads debugger commands from stdin on its own thread, so the
+// emulator keeps running while waiting for them.
+fn console_input() -> Receiver<String> {
+    let (sender, receiver) = channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            match line {
+                Ok(line) => if sender.send(line).is_err() { return },
+                Err(_) => return,
+            }
+        }
+    });
+    return receiver;
+}
+
+fn main() -> () {
+    let args: Vec<String> = std::env::args().collect();
+    let file = match arg_value(&args, "--rom") {
+        Some(rom) => rom.to_string(),
+        None => select_rom(),
+    };
+
+    // --patch <file> applies an IPS, UPS or BPS patch, which is otherwise
+    // looked for next to the ROM with the same name.
+    let patch = arg_value(&args, "--patch").map(Path::new);
+    let entry = select_entry(&file, &args);
+    let mut gb = match Cart::open(file.as_str(), entry.as_deref(), patch) {
+        Ok(cart) => Gameboy::from_cart(cart),
+        Err(e) => {
+            println!("{}", e);
+            exit(1);
+        }
+    };
+
+    // --headless runs without a window, for test ROMs reporting over serial.
+    if args.iter().any(|arg| arg == "--headless") {
+        let frames = arg_value(&args, "--frames")
+            .and_then(|frames| frames.parse().ok())
+            .unwrap_or(HEADLESS_FRAMES);
+        let passed = run_headless(&mut gb, frames, arg_value(&args, "--serial-out"));
+        exit(if passed { 0 } else { 1 });
+    }
+
+    // --printer <directory> plugs in a Game Boy Printer saving to directory.
+    // It takes the link port, so it can't be used with a link cable.
+    let printer = arg_value(&args, "--printer");
+    let linked = arg_value(&args, "--link-listen").is_some() || arg_value(&args, "--link-connect").is_some();
+    if printer.is_some() && linked {
+        println!("--printer can't be used with --link-listen or --link-connect, only one can be plugged in");
+        exit(1);
+    }
+    if let Some(link) = link_cable(&args) {
+        gb.connect_serial(Box::new(link));
+    }
+    if let Some(directory) = printer {
+        gb.connect_serial(Box::new(Printer::new(PathBuf::from(directory))));
+    }
+    // --cheats <file> loads a cheat file, which is otherwise looked for next
+    // to the ROM like its save.
+    let cheat_path = match arg_value(&args, "--cheats") {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(format!("{}.cht", gb.memory.cart.filename)),
+    };
+    if cheat_path.exists() {
+        match load_cheats(&cheat_path) {
+            Ok(cheats) => {
+                println!("Loaded {} cheats from {}", cheats.len(), cheat_path.display());
+                gb.set_cheats(cheats);
+            }
+            Err(e) => println!("Unable to load cheats from {}: {}", cheat_path.display(), e),
+        }
+    }
 
-    let mut gb = Gameboy::new(
-        file.as_str(),
-    );
+    // --console takes debugger commands on stdin, like RAM searches.
+    let commands = if args.iter().any(|arg| arg == "--console") {
+        println!("Debugger console ready, type help for commands");
+        Some(console_input())
+    } else {
+        None
+    };
+    let mut console = Console::new();
 
     let event_loop = EventLoop::new();
     let mut input = WinitInputHelper::new();
@@ -55,14 +199,17 @@ fn main() -> () {
     event_loop.run(move |event, _, control_flow| {
         if let Event::RedrawRequested(_) = event {
             let frame = pixels.get_frame();
-            for x in 0..gb.rendered_screen.len() {
-                for y in 0..gb.rendered_screen[x].len() {
-                    let color = gb.rendered_screen[x][y];
-                    let index = 4 * (x + (y * (SCREEN_WIDTH as usize)));
-                    frame[index] = color.r;
-                    frame[index + 1] = color.g;
-                    frame[index + 2] = color.b;
-                    frame[index + 3] = 0xFF;
+            // Keep showing the previous frame while the LCD is starting up.
+            if !gb.frame_skipped {
+                for x in 0..gb.rendered_screen.len() {
+                    for y in 0..gb.rendered_screen[x].len() {
+                        let color = gb.rendered_screen[x][y];
+                        let index = 4 * (x + (y * (SCREEN_WIDTH as usize)));
+                        frame[index] = color.r;
+                        frame[index + 1] = color.g;
+                        frame[index + 2] = color.b;
+                        frame[index + 3] = 0xFF;
+                    }
                 }
             }
             if pixels.render().is_err() {
@@ -89,6 +236,28 @@ fn main() -> () {
                 gb.swap_palette();
             }
 
+            // Toggle cheats, saving whether they are enabled to the cheat file
+            for (i, key) in CHEAT_KEYS.iter().enumerate() {
+                if input.key_pressed(*key) {
+                    if let Some(enabled) = gb.toggle_cheat(i) {
+                        println!("{} {}", gb.cheats[i].name, if enabled { "enabled" } else { "disabled" });
+                        if let Err(e) = save_cheats(&cheat_path, &gb.cheats) {
+                            println!("Unable to save cheats to {}: {}", cheat_path.display(), e);
+                        }
+                    }
+                }
+            }
+
+            // Run the debugger commands typed in since the last frame
+            if let Some(commands) = &commands {
+                for line in commands.try_iter() {
+                    let output = console.execute(&gb, &line);
+                    if !output.is_empty() {
+                        println!("{}", output);
+                    }
+                }
+            }
+
             // Set speed up
             if input.key_pressed(VirtualKeyCode::Space) {
                 gb.toggle_speed(true);
@@ -98,8 +267,10 @@ fn main() -> () {
                 gb.toggle_speed(false);
             }
 
-            // Close events
+            // Close events, unplugging the printer so it saves what it has
+            // printed
             if input.quit() {
+                gb.disconnect_serial();
                 *control_flow = ControlFlow::Exit;
                 return;
             }
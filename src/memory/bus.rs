@@ -0,0 +1,138 @@
+use crate::memory::MemoryAddr;
+
+// Device is what answers accesses to a range of addresses on the bus. Other
+// than Plugged, these are only tags: the accesses are still carried out by
+// Gameboy's read and write, as the cart, PPU, DMA and IO registers share the
+// Gameboy's state.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Device {
+    // The cartridge ROM, which also takes writes to the banking controller.
+    Cart,
+    Vram,
+    CartRam,
+    Wram,
+    // A mirror of 0xC000 to 0xDDFF.
+    EchoRam,
+    Oam,
+    // The unusable area between OAM and the IO registers.
+    Unusable,
+    Io,
+    Hram,
+    // IE at 0xFFFF.
+    InterruptEnable,
+    // A MemoryDevice plugged into the bus, by its index.
+    Plugged(usize),
+}
+
+// MemoryDevice is a device that answers the accesses to the addresses it is
+// plugged into on its own, without the rest of the Gameboy.
+pub trait MemoryDevice {
+    fn read(&self, addr: MemoryAddr) -> u8;
+    fn write(&mut self, addr: MemoryAddr, value: u8);
+}
+
+// MemoryHook is told about every access the CPU makes, once it is made.
+pub trait MemoryHook {
+    fn read(&mut self, addr: MemoryAddr, value: u8);
+    fn write(&mut self, addr: MemoryAddr, value: u8);
+    // cycle is called at the start of every machine cycle, before any access
+    // made in it.
+    fn cycle(&mut self) {}
+}
+
+// Bus decides which device answers each address, and tells the hooks about
+// the CPU's accesses. Addresses nothing is mapped to read 0xFF and ignore
+// writes. Only plugged MemoryDevices can be used without a Gameboy, the
+// built in devices can't be tested on their own yet.
+pub struct Bus {
+    // The index in kinds of the device mapped at each address, or 0 for none.
+    table: Vec<u8>,
+    kinds: Vec<Option<Device>>,
+    devices: Vec<Box<dyn MemoryDevice>>,
+    hooks: Vec<Box<dyn MemoryHook>>,
+}
+
+impl Bus {
+    // new returns a bus with the Gameboy's memory map.
+    pub fn new() -> Bus {
+        let mut bus = Bus::empty();
+        bus.map(0x0000, 0x7FFF, Device::Cart);
+        bus.map(0x8000, 0x9FFF, Device::Vram);
+        bus.map(0xA000, 0xBFFF, Device::CartRam);
+        bus.map(0xC000, 0xDFFF, Device::Wram);
+        bus.map(0xE000, 0xFDFF, Device::EchoRam);
+        bus.map(0xFE00, 0xFE9F, Device::Oam);
+        bus.map(0xFEA0, 0xFEFF, Device::Unusable);
+        bus.map(0xFF00, 0xFF7F, Device::Io);
+        bus.map(0xFF80, 0xFFFE, Device::Hram);
+        bus.map(0xFFFF, 0xFFFF, Device::InterruptEnable);
+        return bus;
+    }
+
+    pub fn empty() -> Bus {
+        return Bus {
+            table: vec![0; 0x10000],
+            kinds: vec![None],
+            devices: Vec::new(),
+            hooks: Vec::new(),
+        };
+    }
+
+    // map maps the addresses from start to end to a device, in place of
+    // whatever was mapped there before.
+    pub fn map(&mut self, start: MemoryAddr, end: MemoryAddr, device: Device) {
+        let index = match self.kinds.iter().position(|kind| *kind == Some(device)) {
+            Some(index) => index,
+            None => {
+                self.kinds.push(Some(device));
+                self.kinds.len() - 1
+            }
+        };
+        assert!(index <= u8::MAX as usize, "Too many devices mapped to the bus");
+        self.table[start as usize..=end as usize].fill(index as u8);
+    }
+
+    // plug maps a MemoryDevice to the addresses from start to end.
+    pub fn plug(&mut self, start: MemoryAddr, end: MemoryAddr, device: Box<dyn MemoryDevice>) {
+        self.devices.push(device);
+        self.map(start, end, Device::Plugged(self.devices.len() - 1));
+    }
+
+    pub fn add_hook(&mut self, hook: Box<dyn MemoryHook>) {
+        self.hooks.push(hook);
+    }
+
+    // device returns the device mapped at addr.
+    pub fn device(&self, addr: MemoryAddr) -> Option<Device> {
+        return self.kinds[self.table[addr as usize] as usize];
+    }
+
+    pub fn read_plugged(&self, index: usize, addr: MemoryAddr) -> u8 {
+        return self.devices[index].read(addr);
+    }
+
+    pub fn write_plugged(&mut self, index: usize, addr: MemoryAddr, value: u8) {
+        self.devices[index].write(addr, value);
+    }
+
+    // hook_cycle tells the hooks a machine cycle has started.
+    pub fn hook_cycle(&mut self) {
+        for hook in self.hooks.iter_mut() {
+            hook.cycle();
+        }
+    }
+
+    // hook_read tells the hooks about a read made by the CPU.
+    pub fn hook_read(&mut self, addr: MemoryAddr, value: u8) {
+        for hook in self.hooks.iter_mut() {
+            hook.read(addr, value);
+        }
+    }
+
+    // hook_write tells the hooks about a write made by the CPU.
+    pub fn hook_write(&mut self, addr: MemoryAddr, value: u8) {
+        for hook in self.hooks.iter_mut() {
+            hook.write(addr, value);
+        }
+    }
+}
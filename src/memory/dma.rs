@@ -0,0 +1,81 @@
+use crate::gameboy::Gameboy;
+use crate::memory::MemoryAddr;
+
+// OAM_DMA_LENGTH is the number of bytes copied into OAM, one per machine
+// cycle.
+const OAM_DMA_LENGTH: u16 = 0xA0;
+
+// OamDma models the DMA transfer into OAM started by writing to 0xFF46. The
+// transfer starts one machine cycle after the write and then copies a byte
+// every cycle for 160 cycles. While it runs the CPU can only use the upper
+// IO area and HRAM, everything else on the bus reads the byte being copied.
+pub struct OamDma {
+    pub active: bool,
+    // The source of the transfer in progress.
+    source: MemoryAddr,
+    // The next byte to copy, from 0 to 159.
+    index: u16,
+    // The last byte copied, which is what the CPU sees on the bus.
+    pub byte: u8,
+    // A transfer that has been requested but not started yet, along with
+    // the cycles left until it starts. A transfer already running carries
+    // on until the new one takes over.
+    pending: Option<(MemoryAddr, u8)>,
+}
+
+impl OamDma {
+    pub fn new() -> OamDma {
+        return OamDma {
+            active: false,
+            source: 0,
+            index: 0,
+            byte: 0xFF,
+            pending: None,
+        };
+    }
+
+    pub fn start(&mut self, value: u8) {
+        let mut source = (value as u16) << 8;
+        // Sources past the end of WRAM read from WRAM, like echo RAM.
+        if source >= 0xE000 {
+            source -= 0x2000;
+        }
+        self.pending = Some((source, 2));
+    }
+
+    // blocks returns whether the CPU is kept off the bus at the given
+    // address by the transfer.
+    pub fn blocks(&self, addr: MemoryAddr) -> bool {
+        return self.active && addr < 0xFF00;
+    }
+}
+
+impl Gameboy {
+    // update_dma runs the OAM DMA for one machine cycle.
+    pub fn update_dma(&mut self) {
+        if let Some((source, delay)) = self.memory.dma.pending {
+            if delay > 1 {
+                self.memory.dma.pending = Some((source, delay - 1));
+            } else {
+                self.memory.dma.pending = None;
+                self.memory.dma.active = true;
+                self.memory.dma.source = source;
+                self.memory.dma.index = 0;
+            }
+        }
+
+        if !self.memory.dma.active {
+            return;
+        }
+        if self.memory.dma.index == OAM_DMA_LENGTH {
+            self.memory.dma.active = false;
+            return;
+        }
+
+        let index = self.memory.dma.index;
+        let byte = self.read(self.memory.dma.source + index);
+        self.memory.oam[index as usize] = byte;
+        self.memory.dma.byte = byte;
+        self.memory.dma.index += 1;
+    }
+}
@@ -1,9 +1,17 @@
+pub mod bus;
+pub mod dma;
 mod read;
+pub mod timer;
+#[cfg(test)]
+mod tests;
 mod write;
 use crate::bit_functions::{test, val};
 use crate::cart::controller::{Cart, CBG_MODE};
 use crate::gameboy::Gameboy;
 use crate::input::Input;
+use crate::memory::dma::OamDma;
+use crate::memory::timer::Timer;
+use crate::cpu::SPEED_SWITCH_CYCLES;
 
 pub const CLOCK_SPEED: usize = 4194304;
 pub const FRAMES_PER_SECOND: usize = 60;
@@ -11,11 +19,16 @@ pub const DIV: u16 = 0xFF04;
 pub const TIMA: u16 = 0xFF05;
 pub const TMA: u16 = 0xFF06;
 pub const TAC: u16 = 0xFF07;
+// HDMA_BLOCK_CYCLES is the number of cycles the CPU is stalled for each 16
+// byte block copied by HDMA.
+const HDMA_BLOCK_CYCLES: usize = 32;
 
 pub type MemoryAddr = u16;
 
-pub struct Timer {
-    pub value: usize,
+// wram_bank_index returns where in WRAM an address in 0xD000 to 0xDFFF is
+// stored for the given bank.
+pub fn wram_bank_index(addr: MemoryAddr, bank: u8) -> usize {
+    return (addr - 0xC000) as usize + bank as usize * 0x1000;
 }
 
 pub struct Speed {
@@ -24,37 +37,36 @@ pub struct Speed {
     pub cycle_frames: usize,
 }
 
-impl Timer {
-    pub fn reset_timer(&mut self) {
-        self.value = 0;
-    }
-}
-
 pub struct MMU {
     pub cart: Cart,
     pub timer: Timer,
+    pub dma: OamDma,
     pub input: Input,
     pub speed: Speed,
     pub ram: [u8; 0x100],
     pub vram: [u8; 0x4000],
     vram_bank: u8,
 
-    wram: [u8; 0x9000],
+    pub wram: [u8; 0x9000],
     wram_bank: u8,
 
-    oam: [u8; 0x100],
+    pub oam: [u8; 0x100],
+    // Whether the CPU is kept from VRAM, OAM and CGB palette RAM while the
+    // PPU is using them. Debugging tools can turn this off to look at memory
+    // at any time.
+    pub access_blocking: bool,
 
+    // The CGB HDMA source and destination, advanced as data is copied. The
+    // destination is an offset into VRAM.
+    hdma_source: u16,
+    hdma_destination: u16,
+    // The number of 16 byte blocks left for the HDMA to copy, minus one.
     hdma_len: u8,
     hdma_active: bool,
 }
 
 impl MMU {
     pub fn init(&mut self) {
-        self.ram[(0x04) as usize] = 0x1E;
-        self.ram[(0x05) as usize] = 0x00;
-        self.ram[(0x06) as usize] = 0x00;
-        self.ram[(0x07) as usize] = 0xF8;
-        self.ram[(0x0F) as usize] = 0xE1;
         self.ram[(0x10) as usize] = 0x80;
         self.ram[(0x11) as usize] = 0xBF;
         self.ram[(0x12) as usize] = 0xF3;
@@ -83,15 +95,15 @@ impl MMU {
         self.ram[(0x49) as usize] = 0xFF;
         self.ram[(0x4A) as usize] = 0x00;
         self.ram[(0x4B) as usize] = 0x00;
-        self.ram[(0xFF) as usize] = 0x00;
 
         self.wram_bank = 1;
     }
 
-    pub fn new(filename: &str) -> MMU {
+    pub fn new(cart: Cart) -> MMU {
         return MMU {
-            cart: Cart::new(filename),
-            timer: Timer { value: 0 },
+            cart,
+            timer: Timer::new(),
+            dma: OamDma::new(),
             input: Input { mask: 0xFF },
             speed: Speed {
                 current: 0,
@@ -104,6 +116,9 @@ impl MMU {
             wram: [0; 0x9000],
             wram_bank: 0,
             oam: [0; 0x100],
+            access_blocking: true,
+            hdma_source: 0,
+            hdma_destination: 0,
             hdma_len: 0,
             hdma_active: false,
         };
@@ -116,79 +131,135 @@ impl MMU {
 
 impl Gameboy {
 
-    pub fn check_speed(&mut self) {
+    // vram_index returns where in VRAM an address in 0x8000 to 0x9FFF is
+    // stored, in the current bank.
+    pub fn vram_index(&self, addr: MemoryAddr) -> usize {
+        return (addr - 0x8000) as usize + self.memory.vram_bank as usize * 0x2000;
+    }
+
+    // wram_index returns where in WRAM an address in 0xC000 to 0xDFFF is
+    // stored. 0xD000 to 0xDFFF is banked on the CGB.
+    pub fn wram_index(&self, addr: MemoryAddr) -> usize {
+        if addr < 0xD000 {
+            return (addr - 0xC000) as usize;
+        }
+        return wram_bank_index(addr, self.memory.wram_bank);
+    }
+
+    // vram_accessible returns whether the CPU can use VRAM, which is locked
+    // during pixel transfer.
+    pub fn vram_accessible(&self) -> bool {
+        return !self.memory.access_blocking || self.ppu.mode != 3;
+    }
+
+    // oam_accessible returns whether the CPU can use OAM, which is locked
+    // during the OAM scan and pixel transfer.
+    pub fn oam_accessible(&self) -> bool {
+        return !self.memory.access_blocking || self.ppu.mode < 2;
+    }
+
+    // palette_accessible returns whether the CPU can use CGB palette RAM,
+    // which is locked during pixel transfer.
+    pub fn palette_accessible(&self) -> bool {
+        return self.vram_accessible();
+    }
+
+    // check_speed switches between normal and double speed if a switch has
+    // been prepared through KEY1, returning whether it did so.
+    pub fn check_speed(&mut self) -> bool {
         if self.memory.speed.prepare {
             self.memory.speed.prepare = false;
             self.memory.speed.current = if self.memory.speed.current == 0 { 1 } else { 0 };
-            self.halted = false;
+            self.cpu.stall = SPEED_SWITCH_CYCLES;
+            return true;
         }
+        return false;
     }
-    pub fn is_clock_enabled(&self) -> bool {
-        return test(self.read(TAC), 2);
-    }
-
-    pub fn get_clock_freq(&self) -> u8 {
-        return self.read(TAC) & 0x3;
+    // write_hdma updates the HDMA source and destination through 0xFF51 to
+    // 0xFF54. The lower 4 bits of both are ignored, and the destination is
+    // always in VRAM.
+    pub fn write_hdma(&mut self, addr: MemoryAddr, value: u8) {
+        let source = self.memory.hdma_source;
+        let destination = self.memory.hdma_destination;
+        match addr {
+            0xFF51 => self.memory.hdma_source = ((value as u16) << 8) | (source & 0xFF),
+            0xFF52 => self.memory.hdma_source = (source & 0xFF00) | (value as u16 & 0xF0),
+            0xFF53 => self.memory.hdma_destination = ((value as u16 & 0x1F) << 8) | (destination & 0xFF),
+            _ => self.memory.hdma_destination = (destination & 0x1F00) | (value as u16 & 0xF0),
+        }
     }
 
-    fn transfer(&mut self, len: u16) {
-        let mut source = ((self.memory.ram[0x51_usize] as u16) << 8)
-            | (((self.memory.ram[0x52_usize]) as u16) & 0xFFF0_u16);
-        let mut destination = ((self.memory.ram[0x53_usize] as u16) << 8)
-            | ((self.memory.ram[0x54_usize] as u16) & 0x1FF0);
-        destination = destination.wrapping_add(0x8000);
-
-        let mut i = 0_u16;
-        while i < len {
-            let val = self.read(source);
-            self.write(destination, val);
-            destination = destination.wrapping_add(1);
-            source = source.wrapping_add(1);
-            i = i.wrapping_add(1);
+    // transfer copies one 16 byte block for the HDMA, returning false if the
+    // destination ran past the end of VRAM, which ends the transfer. The CPU
+    // is stalled for 8 machine cycles per block, or 16 in double speed mode.
+    fn transfer(&mut self) -> bool {
+        for _ in 0..0x10 {
+            let val = self.read(self.memory.hdma_source);
+            let offset = (self.memory.vram_bank as usize) * 0x2000 + self.memory.hdma_destination as usize;
+            self.memory.vram[offset] = val;
+            self.memory.hdma_source = self.memory.hdma_source.wrapping_add(1);
+            self.memory.hdma_destination = self.memory.hdma_destination.wrapping_add(1);
         }
+        self.cpu.dma_stall += HDMA_BLOCK_CYCLES << self.memory.speed.current;
 
-        self.memory.ram[0x51] = (source >> 8) as u8;
-        self.memory.ram[0x52] = (source & 0xFF) as u8;
-        self.memory.ram[0x53] = (destination >> 8) as u8;
-        self.memory.ram[0x54] = (destination & 0xF0) as u8;
+        if self.memory.hdma_destination >= 0x2000 {
+            self.memory.hdma_destination &= 0x1FFF;
+            return false;
+        }
+        return true;
     }
 
     pub fn cgb_dma_transfer(&mut self, value: u8) {
         if self.memory.hdma_active && val(value, 7) == 0 {
+            // Writing with bit 7 clear cancels a running HBlank DMA, leaving
+            // the remaining length readable.
             self.memory.hdma_active = false;
-            self.memory.ram[0x55_usize] |= 0x80; // Set bit 7
+            self.memory.ram[0x55] = self.memory.hdma_len | 0x80;
             return;
         }
 
-        let len = ((value as u16) & 0x7F).wrapping_add(1).wrapping_mul(0x10);
-        if value >> 7 == 0 {
-            self.transfer(len);
-            self.memory.ram[0x55] = 0xFF;
-        } else {
-            self.memory.hdma_len = value as u8;
+        self.memory.hdma_len = value & 0x7F;
+        if val(value, 7) == 0 {
+            // General purpose DMA copies everything at once.
             self.memory.hdma_active = true;
+            while self.memory.hdma_active {
+                self.step_hdma();
+            }
+            return;
+        }
+
+        self.memory.hdma_active = true;
+        self.memory.ram[0x55] = self.memory.hdma_len;
+        // A block is copied straight away if the transfer is started during
+        // HBlank, or while the LCD is off. In the latter case the rest wait
+        // for the LCD to be turned back on, as blocks are only copied as it
+        // enters HBlank, which it never does while off.
+        if !test(self.memory.ram[0x40], 7) || self.ppu.mode == 0 {
+            self.step_hdma();
         }
     }
 
     pub fn dma_transfer(&mut self, val: u8) {
-        let address = (val as u16) << 8; // (data * 100)
-
-        for i in  0_u16..0xA0 {
-            let val = self.read(address.wrapping_add(i));
-            self.write(0xFE00_u16.wrapping_add(i), val);
-        }
+        self.memory.ram[0x46] = val;
+        self.memory.dma.start(val);
     }
 
+    // hdma_transfer is called as the LCD enters HBlank to copy the next
+    // block of a running HBlank DMA. It is not called while the LCD is off,
+    // which pauses the transfer until it is turned back on.
     pub fn hdma_transfer(&mut self) {
         if self.memory.hdma_active {
-            self.transfer(0x10);
-            if self.memory.hdma_len > 0 {
-                self.memory.hdma_len = self.memory.hdma_len.wrapping_sub(1);
-                self.memory.ram[0x55_usize] = self.memory.hdma_len;
-            } else {
-                self.memory.ram[0x55_usize] = 0xFF;
-                self.memory.hdma_active = false;
-            }
+            self.step_hdma();
+        }
+    }
+
+    fn step_hdma(&mut self) {
+        if self.transfer() && self.memory.hdma_len > 0 {
+            self.memory.hdma_len -= 1;
+            self.memory.ram[0x55] = self.memory.hdma_len;
+        } else {
+            self.memory.ram[0x55] = 0xFF;
+            self.memory.hdma_active = false;
         }
     }
 }
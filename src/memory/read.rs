@@ -1,52 +1,94 @@
 use crate::gameboy::Gameboy;
-use crate::memory::MemoryAddr;
+use crate::memory::{MemoryAddr, DIV, TIMA, TMA, TAC};
 use crate::bit_functions::b;
+use crate::memory::bus::Device;
 
-impl Gameboy {
-    pub fn read(&self, addr: MemoryAddr) -> u8 {
-        return match addr {
-            // BIOS (256b)/ROM0
-            0x0000..=0x7FFF => {
-                self.memory.cart.read(addr)
-            }
-            // ROM0
-            0x8000..=0x9FFF => {
-                let offset = (self.memory.vram_bank as u16).wrapping_mul(0x2000);
-                self.memory.vram[addr.wrapping_sub(0x8000).wrapping_add(offset) as usize]
-            }
+// DMG_IO_READ_MASKS holds the bits that always read as 1 for each IO
+// register from 0xFF00 to 0xFF7F on the DMG. Unmapped registers read 0xFF.
+const DMG_IO_READ_MASKS: [u8; 0x80] = [
+    // P1, SB, SC, DIV, TIMA, TMA, TAC, IF
+    0xC0, 0x00, 0x7E, 0xFF, 0x00, 0x00, 0x00, 0xF8, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xE0,
+    // NR10 to NR34, with 0xFF15 and 0xFF1F unused
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, 0xFF, 0x3F, 0x00, 0xFF, 0xBF, 0x7F, 0xFF, 0x9F, 0xFF, 0xBF, 0xFF,
+    // NR41 to NR52, then unused up to 0xFF2F
+    0xFF, 0x00, 0x00, 0xBF, 0x00, 0x00, 0x70, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    // Wave RAM
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    // LCDC, STAT, SCY, SCX, LY, LYC, DMA, BGP, OBP0, OBP1, WY, WX
+    0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
 
-            // External RAM (8k)
-            0xA000..=0xBFFF => {
-                self.memory.cart.read(addr)
-            }
+// CGB_IO_READ_MASKS is DMG_IO_READ_MASKS with the CGB only registers mapped.
+const CGB_IO_READ_MASKS: [u8; 0x80] = [
+    // P1, SB, SC, DIV, TIMA, TMA, TAC, IF
+    0xC0, 0x00, 0x7C, 0xFF, 0x00, 0x00, 0x00, 0xF8, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xE0,
+    // NR10 to NR34, with 0xFF15 and 0xFF1F unused
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, 0xFF, 0x3F, 0x00, 0xFF, 0xBF, 0x7F, 0xFF, 0x9F, 0xFF, 0xBF, 0xFF,
+    // NR41 to NR52, then unused up to 0xFF2F
+    0xFF, 0x00, 0x00, 0xBF, 0x00, 0x00, 0x70, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    // Wave RAM
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    // LCDC to WX, KEY1, VBK
+    0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x7E, 0xFF, 0xFE,
+    // HDMA1 to HDMA5, RP
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x3C, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    // BCPS, BCPD, OCPS, OCPD, OPRI
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x40, 0x00, 0x40, 0x00, 0xFE, 0xFF, 0xFF, 0xFF,
+    // SVBK, undocumented registers, PCM12, PCM34
+    0xF8, 0xFF, 0x00, 0x00, 0x00, 0x8F, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
 
-            // Working RAM (8k)
-            0xC000..=0xCFFF => {
-                self.memory.wram[(addr.wrapping_sub(0xC000)) as usize]
-            }
-            // Working RAM shadow
-            0xD000..=0xDFFF => {
-                self.memory.wram[((addr - 0xC000)
-                    + (self.memory.wram_bank as u16).wrapping_mul(0x1000))
-                    as usize]
+impl Gameboy {
+    pub fn read(&self, addr: MemoryAddr) -> u8 {
+        return match self.bus.device(addr) {
+            Some(Device::Cart) | Some(Device::CartRam) => self.memory.cart.read(addr),
+            Some(Device::Vram) => {
+                if !self.vram_accessible() {
+                    return 0xFF;
+                }
+                self.memory.vram[self.vram_index(addr)]
             }
-            // Mirror of C000~~DDFF (Echo RAM)
+            Some(Device::Wram) => self.memory.wram[self.wram_index(addr)],
             // Nintendo prohibits use of this area
-            0xE000..=0xFDFF => {
-                0xFF
-            }
-            // Sprite attribute table (OAM)
-            0xFE00..=0xFE9F => {
+            Some(Device::EchoRam) => self.read(addr - 0x2000),
+            Some(Device::Oam) => {
+                if !self.oam_accessible() {
+                    return 0xFF;
+                }
                 self.memory.oam[(addr - 0xFE00) as usize]
             }
-            0xFEA0..=0xFEFF => {
-                0xFF
+            // The DMG reads 0, while the CGB repeats the upper nibble of the
+            // lower address byte.
+            Some(Device::Unusable) => {
+                if !self.oam_accessible() {
+                    0xFF
+                } else if self.cgb_mode {
+                    let nibble = (addr as u8) & 0xF0;
+                    nibble | (nibble >> 4)
+                } else {
+                    0x00
+                }
             }
-            _ => self.read_upper_ram(addr),
+            Some(Device::Io) => self.read_upper_ram(addr),
+            Some(Device::Hram) => self.memory.ram[(addr - 0xFF00) as usize],
+            Some(Device::InterruptEnable) => self.interrupts.enabled,
+            Some(Device::Plugged(index)) => self.bus.read_plugged(index, addr),
+            None => 0xFF,
         }
     }
 
+    // read_upper_ram reads the IO registers from 0xFF00 to 0xFF7F. Bits that
+    // are unused always read as 1.
     pub fn read_upper_ram(&self, addr: MemoryAddr) -> u8 {
+        let value = self.read_register(addr);
+        let masks = if self.cgb_mode { &CGB_IO_READ_MASKS } else { &DMG_IO_READ_MASKS };
+        return value | masks[(addr - 0xFF00) as usize];
+    }
+
+    fn read_register(&self, addr: MemoryAddr) -> u8 {
         match addr {
             0xFF00 => {
                 return self
@@ -54,41 +96,51 @@ impl Gameboy {
                     .input
                     .joypad_value(self.memory.ram[(0x00) as usize]);
             }
-            0xFF10..=0xFF26 => {
-                // TODO Read Sound
-            }
-            0xFF30..=0xFF3F => {} // TODO read wave form
-            0xFF0F => return self.memory.ram[(0x0F) as usize] | 0xE0,
-            0xFF72..=0xFF77 => return 0,
+            DIV => return self.memory.timer.div(),
+            TIMA => return self.memory.timer.tima,
+            TMA => return self.memory.timer.tma,
+            TAC => return self.memory.timer.tac,
+            0xFF01 => return self.serial.data,
+            0xFF02 => return self.serial.control,
+            0xFF0F => return self.interrupts.read_flags(),
+            // PCM12 and PCM34 read the channel outputs, which are not emulated.
+            0xFF76..=0xFF77 => return 0,
             0xFF68 => {
                 if self.cgb_mode {
-                    return self.bg_palette.index;
+                    return self.bg_palette.index | b(self.bg_palette.inc) << 7;
                 }
                 return 0;
             }
             0xFF69 => {
                 if self.cgb_mode {
+                    if !self.palette_accessible() {
+                        return 0xFF;
+                    }
                     return self.bg_palette.read();
                 }
                 return 0;
             }
             0xFF6A => {
                 if self.cgb_mode {
-                    self.sprite_palette.index;
+                    return self.sprite_palette.index | b(self.sprite_palette.inc) << 7;
                 }
                 return 0;
             }
             0xFF6B => {
                 if self.cgb_mode {
+                    if !self.palette_accessible() {
+                        return 0xFF;
+                    }
                     return self.sprite_palette.read();
                 }
                 return 0;
             }
             0xFF4D => return self.memory.speed.current << 7 | b(self.memory.speed.prepare),
             0xFF4F => return self.memory.vram_bank,
+            // The HDMA source and destination are write only.
+            0xFF51..=0xFF54 => return 0xFF,
             0xFF70 => return self.memory.wram_bank,
             _ => return self.memory.ram[(addr - 0xFF00) as usize],
         }
-        return 0;
     }
 }
@@ -0,0 +1,143 @@
+use std::sync::{Arc, Mutex};
+use crate::cart::controller::Cart;
+use crate::gameboy::Gameboy;
+use crate::memory::bus::{Bus, Device, MemoryDevice, MemoryHook};
+use crate::memory::MemoryAddr;
+
+fn gameboy() -> Gameboy {
+    return Gameboy::from_cart(Cart::from_rom(vec![0; 0x8000], "").unwrap());
+}
+
+// Flat is a plain block of memory starting at 0xC000.
+struct Flat {
+    memory: Vec<u8>,
+}
+
+impl MemoryDevice for Flat {
+    fn read(&self, addr: MemoryAddr) -> u8 {
+        return self.memory[(addr - 0xC000) as usize];
+    }
+
+    fn write(&mut self, addr: MemoryAddr, value: u8) {
+        self.memory[(addr - 0xC000) as usize] = value;
+    }
+}
+
+// Accesses records what a hook is told about.
+struct Accesses {
+    accesses: Arc<Mutex<Vec<(char, MemoryAddr, u8)>>>,
+}
+
+impl MemoryHook for Accesses {
+    fn read(&mut self, addr: MemoryAddr, value: u8) {
+        self.accesses.lock().unwrap().push(('r', addr, value));
+    }
+
+    fn write(&mut self, addr: MemoryAddr, value: u8) {
+        self.accesses.lock().unwrap().push(('w', addr, value));
+    }
+}
+
+#[test]
+fn maps_the_gameboy_memory_map() {
+    let bus = Bus::new();
+    assert_eq!(bus.device(0x0000), Some(Device::Cart));
+    assert_eq!(bus.device(0x9FFF), Some(Device::Vram));
+    assert_eq!(bus.device(0xA000), Some(Device::CartRam));
+    assert_eq!(bus.device(0xDFFF), Some(Device::Wram));
+    assert_eq!(bus.device(0xFDFF), Some(Device::EchoRam));
+    assert_eq!(bus.device(0xFE9F), Some(Device::Oam));
+    assert_eq!(bus.device(0xFEA0), Some(Device::Unusable));
+    assert_eq!(bus.device(0xFF7F), Some(Device::Io));
+    assert_eq!(bus.device(0xFF80), Some(Device::Hram));
+    assert_eq!(bus.device(0xFFFF), Some(Device::InterruptEnable));
+    assert_eq!(Bus::empty().device(0x0000), None);
+}
+
+#[test]
+fn mapping_replaces_part_of_earlier_mappings() {
+    let mut bus = Bus::new();
+    bus.map(0x7F00, 0x80FF, Device::Hram);
+    assert_eq!(bus.device(0x7EFF), Some(Device::Cart));
+    assert_eq!(bus.device(0x7F00), Some(Device::Hram));
+    assert_eq!(bus.device(0x80FF), Some(Device::Hram));
+    assert_eq!(bus.device(0x8100), Some(Device::Vram));
+    assert_eq!(bus.device(0xFF80), Some(Device::Hram));
+}
+
+#[test]
+fn echo_ram_mirrors_wram() {
+    let mut gb = gameboy();
+    gb.write(0xC123, 0x42);
+    assert_eq!(gb.read(0xE123), 0x42);
+    gb.write(0xF000, 0x24);
+    assert_eq!(gb.read(0xD000), 0x24);
+}
+
+#[test]
+fn plugged_devices_replace_what_was_mapped() {
+    let mut gb = gameboy();
+    gb.write(0xC000, 0x11);
+    gb.bus.plug(0xC000, 0xCFFF, Box::new(Flat { memory: vec![0; 0x1000] }));
+    assert_eq!(gb.read(0xC000), 0x00);
+
+    gb.write(0xC000, 0x22);
+    assert_eq!(gb.read(0xC000), 0x22);
+    // Echo RAM follows whatever is mapped at 0xC000.
+    assert_eq!(gb.read(0xE000), 0x22);
+    // The rest of WRAM is still there.
+    gb.write(0xD000, 0x33);
+    assert_eq!(gb.read(0xD000), 0x33);
+}
+
+#[test]
+fn unmapped_addresses_read_open_bus() {
+    let mut gb = gameboy();
+    gb.bus = Bus::empty();
+    gb.write(0xC000, 0x42);
+    assert_eq!(gb.read(0xC000), 0xFF);
+}
+
+#[test]
+fn hooks_see_cpu_accesses() {
+    let mut gb = gameboy();
+    let accesses = Arc::new(Mutex::new(Vec::new()));
+    gb.bus.add_hook(Box::new(Accesses { accesses: accesses.clone() }));
+
+    // LD (HL),A
+    gb.write(0xC000, 0x77);
+    gb.cpu.pc = 0xC000;
+    gb.cpu.hl.set_full(0xC100);
+    gb.cpu.af.set_hi(0x42);
+    gb.execute_next_opcode();
+    assert_eq!(*accesses.lock().unwrap(), vec![('r', 0xC000, 0x77), ('w', 0xC100, 0x42)]);
+}
+
+#[test]
+fn hblank_dma_waits_for_lcd_to_be_turned_on() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x143] = 0xC0;
+    let mut gb = Gameboy::from_cart(Cart::from_rom(rom, "").unwrap());
+    for i in 0..0x20 {
+        gb.write(0xC000 + i, i as u8 + 1);
+    }
+    gb.write(0xFF40, 0x11);
+
+    // Copy 2 blocks from 0xC000 to 0x8000. Only the first is copied while
+    // the LCD is off, however long it stays off.
+    gb.write(0xFF51, 0xC0);
+    gb.write(0xFF52, 0x00);
+    gb.write(0xFF53, 0x00);
+    gb.write(0xFF54, 0x00);
+    gb.write(0xFF55, 0x81);
+    gb.update_graphics(70224);
+    assert_eq!(gb.memory.vram[0x0F], 0x10);
+    assert_eq!(gb.memory.vram[0x10], 0x00);
+    assert_eq!(gb.read(0xFF55), 0x00);
+
+    // The second block is copied in the first HBlank once it is back on.
+    gb.write(0xFF40, 0x91);
+    gb.update_graphics(456);
+    assert_eq!(gb.memory.vram[0x1F], 0x20);
+    assert_eq!(gb.read(0xFF55), 0xFF);
+}
@@ -0,0 +1,130 @@
+use crate::bit_functions::test;
+use crate::gameboy::Gameboy;
+use crate::interrupts::TIMER;
+
+// Reload is the state of TIMA after it overflows. TIMA reads 0 for one
+// machine cycle before TMA is loaded into it and the interrupt is requested.
+#[derive(Clone, Copy, PartialEq)]
+enum Reload {
+    None,
+    // TIMA overflowed on the last cycle and currently reads 0.
+    Pending,
+    // TMA is being loaded into TIMA during this cycle.
+    Active,
+}
+
+// Timer models the DIV, TIMA, TMA and TAC registers. DIV is the upper byte
+// of a 16 bit counter incremented every cycle, and TIMA is incremented on the
+// falling edge of the counter bit selected by TAC. Because of this, resetting
+// DIV or changing TAC can also increment TIMA.
+pub struct Timer {
+    pub counter: u16,
+    pub tima: u8,
+    pub tma: u8,
+    pub tac: u8,
+    reload: Reload,
+}
+
+impl Timer {
+    pub fn new() -> Timer {
+        return Timer {
+            counter: 0x1E00,
+            tima: 0,
+            tma: 0,
+            tac: 0xF8,
+            reload: Reload::None,
+        };
+    }
+
+    // signal returns the input of the falling edge detector that drives
+    // TIMA: the counter bit selected by TAC, ANDed with the enable bit.
+    fn signal(&self) -> bool {
+        let bit = match self.tac & 0x3 {
+            0 => 9, // 4096 Hz
+            1 => 3, // 262144 Hz
+            2 => 5, // 65536 Hz
+            _ => 7, // 16384 Hz
+        };
+        return test(self.tac, 2) && (self.counter >> bit) & 1 == 1;
+    }
+
+    fn detect_edge(&mut self, previous: bool) {
+        if previous && !self.signal() {
+            self.increment();
+        }
+    }
+
+    fn increment(&mut self) {
+        self.tima = self.tima.wrapping_add(1);
+        if self.tima == 0 {
+            self.reload = Reload::Pending;
+        }
+    }
+
+    // tick advances the timer by one machine cycle, returning true if the
+    // timer interrupt should be requested.
+    pub fn tick(&mut self) -> bool {
+        let mut interrupt = false;
+        match self.reload {
+            Reload::Pending => {
+                self.tima = self.tma;
+                self.reload = Reload::Active;
+                interrupt = true;
+            }
+            Reload::Active => self.reload = Reload::None,
+            Reload::None => {}
+        }
+
+        let previous = self.signal();
+        self.counter = self.counter.wrapping_add(4);
+        self.detect_edge(previous);
+        return interrupt;
+    }
+
+    pub fn div(&self) -> u8 {
+        return (self.counter >> 8) as u8;
+    }
+
+    pub fn write_div(&mut self) {
+        let previous = self.signal();
+        self.counter = 0;
+        self.detect_edge(previous);
+    }
+
+    pub fn write_tima(&mut self, value: u8) {
+        match self.reload {
+            // Writing in the cycle after an overflow cancels the reload.
+            Reload::Pending => {
+                self.tima = value;
+                self.reload = Reload::None;
+            }
+            // Writes are ignored in the cycle TMA is loaded.
+            Reload::Active => {}
+            Reload::None => self.tima = value,
+        }
+    }
+
+    pub fn write_tma(&mut self, value: u8) {
+        self.tma = value;
+        // A write in the cycle TMA is loaded also reaches TIMA.
+        if self.reload == Reload::Active {
+            self.tima = value;
+        }
+    }
+
+    pub fn write_tac(&mut self, value: u8) {
+        let previous = self.signal();
+        self.tac = value | 0xF8;
+        self.detect_edge(previous);
+    }
+}
+
+impl Gameboy {
+    pub fn update_timers(&mut self, cycles: usize) {
+        for _ in 0..(cycles / 4) {
+            if self.memory.timer.tick() {
+                self.request_interrupt(TIMER);
+            }
+        }
+    }
+}
@@ -2,43 +2,25 @@ use crate::gameboy::Gameboy;
 use crate::memory::MemoryAddr;
 use crate::bit_functions::test;
 use crate::memory::{DIV, TIMA, TMA, TAC};
+use crate::memory::bus::Device;
 
 impl Gameboy {
+    // write_upper_ram writes the IO registers from 0xFF00 to 0xFF7F.
     pub fn write_upper_ram(&mut self, addr: MemoryAddr, value: u8) {
         match addr {
-            0xFEA0..=0xFEFF => {}
-            0xFF10..=0xFF26 => {
-                // TODO: Write Sound
-            }
-            0xFF30..=0xFF3F => {
-                // TODO: Write Wave Form
-            }
-            0xFF02 => {
-                // TODO: Serial transfer control
-            }
-            DIV => {
-                self.memory.timer.reset_timer();
-                self.cpu.divider = 0;
-                self.memory.ram[(DIV - 0xFF00) as usize] = 0
-            }
-            TIMA => {
-                self.memory.ram[(TIMA - 0xFF00) as usize] = value;
-            }
-            TMA => {
-                self.memory.ram[(TMA - 0xFF00) as usize] = value;
-            }
-            TAC => {
-                let current_freq = self.get_clock_freq();
-                self.memory.ram[(TAC - 0xFF00) as usize] = value | 0xF8;
-                let new_freq = self.get_clock_freq();
-                if current_freq != new_freq {
-                    self.memory.timer.reset_timer();
-                }
-            }
-            0xFF41 => {
-                self.memory.ram[(0x41) as usize] = value | 0x80;
-            }
-            0xFF44 => self.memory.ram[(0x44) as usize] = 0,
+            0xFF01 => self.serial.data = value,
+            0xFF02 => self.serial.write_control(value, self.cgb_mode),
+            DIV => self.memory.timer.write_div(),
+            TIMA => self.memory.timer.write_tima(value),
+            TMA => self.memory.timer.write_tma(value),
+            TAC => self.memory.timer.write_tac(value),
+            0xFF0F => {
+                self.interrupts.write_flags(value);
+            }
+            0xFF40 => self.write_lcdc(value),
+            0xFF41 => self.write_stat(value),
+            0xFF44 => {}
+            0xFF45 => self.write_lyc(value),
             0xFF46 => {
                 self.dma_transfer(value);
             }
@@ -52,6 +34,11 @@ impl Gameboy {
                     self.memory.vram_bank = value & 0x1;
                 }
             }
+            0xFF51..=0xFF54 => {
+                if self.cgb_mode {
+                    self.write_hdma(addr, value);
+                }
+            }
             0xFF55 => {
                 if self.cgb_mode {
                     self.cgb_dma_transfer(value);
@@ -64,7 +51,11 @@ impl Gameboy {
             }
             0xFF69 => {
                 if self.cgb_mode {
-                    self.bg_palette.write(value);
+                    if self.palette_accessible() {
+                        self.bg_palette.write(value);
+                    } else {
+                        self.bg_palette.increment();
+                    }
                 }
             }
             0xFF6A => {
@@ -74,7 +65,11 @@ impl Gameboy {
             }
             0xFF6B => {
                 if self.cgb_mode {
-                    self.sprite_palette.write(value);
+                    if self.palette_accessible() {
+                        self.sprite_palette.write(value);
+                    } else {
+                        self.sprite_palette.increment();
+                    }
                 }
             }
             0xFF70 => {
@@ -85,9 +80,12 @@ impl Gameboy {
                     }
                 }
             }
-            0xFF72..=0xFF77 => {
-                //TODO: Need to figure out what to do here
+            0xFF74 => {
+                if self.cgb_mode {
+                    self.memory.ram[0x74] = value;
+                }
             }
+            0xFF76..=0xFF77 => {}
             _ => {
                 self.memory.ram[(addr.wrapping_sub(0xFF00)) as usize] = value;
             }
@@ -95,37 +93,31 @@ impl Gameboy {
     }
 
     pub fn write(&mut self, addr: MemoryAddr, value: u8) {
-        match addr {
-            0..=0x7FFF => {
-                self.memory.cart.write_rom(addr, value);
-            }
-            0x8000..=0x9FFF => {
-                let offset = (self.memory.vram_bank as u16).wrapping_mul(0x2000);
-                self.memory.vram[(addr.wrapping_sub(0x8000).wrapping_add(offset)) as usize] = value
-            }
-            0xA000..=0xBFFF => {
-                self.memory.cart.write_ram(addr, value);
-            }
-            0xC000..=0xCFFF => {
-                self.memory.wram[(addr.wrapping_sub(0xC000)) as usize] = value;
-            }
-            0xD000..=0xDFFF => {
-                self.memory.wram[addr
-                    .wrapping_sub(0xC000)
-                    .wrapping_add((self.memory.wram_bank as u16).wrapping_mul(0x1000))
-                    as usize] = value
-            }
-            0xE000..=0xFDFF => {
-                // TODO: echo RAM
-                //mem.Write(address-0x2000, value)
+        match self.bus.device(addr) {
+            Some(Device::Cart) => self.memory.cart.write_rom(addr, value),
+            Some(Device::Vram) => {
+                if self.vram_accessible() {
+                    let index = self.vram_index(addr);
+                    self.memory.vram[index] = value;
+                }
             }
-            0xFE00..=0xFE9F => self.memory.oam[addr.wrapping_sub(0xFE00) as usize] = value,
-            0xFEA0..=0xFEFF => {
-                // Not usable
+            Some(Device::CartRam) => self.memory.cart.write_ram(addr, value),
+            Some(Device::Wram) => {
+                let index = self.wram_index(addr);
+                self.memory.wram[index] = value;
             }
-            _ => {
-                self.write_upper_ram(addr, value);
+            Some(Device::EchoRam) => self.write(addr - 0x2000, value),
+            Some(Device::Oam) => {
+                if self.oam_accessible() {
+                    self.memory.oam[(addr - 0xFE00) as usize] = value;
+                }
             }
+            Some(Device::Unusable) => {}
+            Some(Device::Io) => self.write_upper_ram(addr, value),
+            Some(Device::Hram) => self.memory.ram[(addr - 0xFF00) as usize] = value,
+            Some(Device::InterruptEnable) => self.interrupts.enabled = value,
+            Some(Device::Plugged(index)) => self.bus.write_plugged(index, addr, value),
+            None => {}
         }
     }
 }
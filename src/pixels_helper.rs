@@ -1,10 +1,10 @@
-use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use gameboy_emulator::graphics::{SCREEN_HEIGHT, SCREEN_WIDTH};
 use winit::dpi::{LogicalPosition, LogicalSize, PhysicalSize};
 use winit::event_loop::EventLoop;
 use std::collections::HashMap;
 use winit::event::VirtualKeyCode;
 use winit::monitor::VideoMode;
-use crate::input::{LEFT, START, SELECT, B, A, DOWN, UP, RIGHT, Button};
+use gameboy_emulator::input::{LEFT, START, SELECT, B, A, DOWN, UP, RIGHT, Button};
 use std::io::SeekFrom::Start;
 use soundio::ChannelId::RightLfe;
 
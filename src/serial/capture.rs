@@ -0,0 +1,61 @@
+use std::sync::{Arc, Mutex};
+use crate::gameboy::Gameboy;
+use crate::serial::SerialDevice;
+
+// SerialCapture records every byte sent over the link port. Test ROMs like
+// blargg's print their results this way.
+pub struct SerialCapture {
+    output: SerialOutput,
+}
+
+// SerialOutput is a handle to the bytes recorded by a SerialCapture, which
+// stays usable after the capture has been plugged into a Gameboy.
+#[derive(Clone)]
+pub struct SerialOutput {
+    bytes: Arc<Mutex<Vec<u8>>>,
+}
+
+impl SerialCapture {
+    pub fn new() -> SerialCapture {
+        return SerialCapture {
+            output: SerialOutput {
+                bytes: Arc::new(Mutex::new(Vec::new())),
+            },
+        };
+    }
+
+    pub fn output(&self) -> SerialOutput {
+        return self.output.clone();
+    }
+}
+
+impl SerialOutput {
+    // text returns the recorded bytes as a string, replacing anything that
+    // isn't valid UTF-8.
+    pub fn text(&self) -> String {
+        return String::from_utf8_lossy(&self.bytes.lock().unwrap()).into_owned();
+    }
+}
+
+impl SerialDevice for SerialCapture {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        self.output.bytes.lock().unwrap().push(byte);
+        // Nothing is sent back, as if no cable was plugged in.
+        return 0xFF;
+    }
+
+    fn external_transfer(&mut self, _byte: u8) -> Option<u8> {
+        return None;
+    }
+}
+
+impl Gameboy {
+    // capture_serial plugs a SerialCapture into the link port, returning the
+    // handle to what it records.
+    pub fn capture_serial(&mut self) -> SerialOutput {
+        let capture = SerialCapture::new();
+        let output = capture.output();
+        self.connect_serial(Box::new(capture));
+        return output;
+    }
+}
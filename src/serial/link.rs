@@ -0,0 +1,49 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use crate::serial::SerialDevice;
+
+// LinkEnd is what one end of a link cable has sent to the other.
+struct LinkEnd {
+    // The byte this end has ready to send, as last seen while it waited for
+    // the other end to clock a transfer.
+    ready: u8,
+    // Bytes the other end has transferred using its own clock, waiting to be
+    // picked up.
+    received: VecDeque<u8>,
+}
+
+// LinkCable connects two Gameboys running in the same process, possibly on
+// different threads. Both ends are SerialDevices.
+pub struct LinkCable {
+    ends: Arc<Mutex<[LinkEnd; 2]>>,
+    side: usize,
+}
+
+impl LinkCable {
+    // pair returns both ends of a new cable.
+    pub fn pair() -> (LinkCable, LinkCable) {
+        let ends = Arc::new(Mutex::new([
+            LinkEnd { ready: 0xFF, received: VecDeque::new() },
+            LinkEnd { ready: 0xFF, received: VecDeque::new() },
+        ]));
+        let first = LinkCable { ends: ends.clone(), side: 0 };
+        let second = LinkCable { ends, side: 1 };
+        return (first, second);
+    }
+}
+
+impl SerialDevice for LinkCable {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        let mut ends = self.ends.lock().unwrap();
+        let other = &mut ends[1 - self.side];
+        other.received.push_back(byte);
+        return other.ready;
+    }
+
+    fn external_transfer(&mut self, byte: u8) -> Option<u8> {
+        let mut ends = self.ends.lock().unwrap();
+        let end = &mut ends[self.side];
+        end.ready = byte;
+        return end.received.pop_front();
+    }
+}
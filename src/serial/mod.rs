@@ -0,0 +1,142 @@
+pub mod capture;
+pub mod link;
+pub mod printer;
+pub mod tcp;
+#[cfg(test)]
+mod tests;
+
+use crate::bit_functions::{reset, test};
+use crate::gameboy::Gameboy;
+use crate::interrupts::SERIAL;
+
+// BIT_CYCLES is the number of cycles it takes to shift a bit with the
+// internal clock at 8192 Hz, and FAST_BIT_CYCLES the same for the 262144 Hz
+// clock available on the CGB.
+const BIT_CYCLES: usize = 512;
+const FAST_BIT_CYCLES: usize = 16;
+
+// SerialDevice is something plugged into the link port, like another
+// Gameboy or a printer. Bytes are exchanged whole, both ends shifting out
+// their byte while shifting in the other's.
+pub trait SerialDevice {
+    // transfer is called once the Gameboy has shifted out a byte using its
+    // own clock, and returns the byte shifted in from the device.
+    fn transfer(&mut self, byte: u8) -> u8;
+
+    // external_transfer is polled while the Gameboy waits for the device to
+    // provide the clock, with the byte the Gameboy has ready to send. It
+    // returns the byte received if the device has clocked a transfer.
+    fn external_transfer(&mut self, byte: u8) -> Option<u8>;
+
+    // update is called as the Gameboy runs, for devices that do something
+    // on their own after a while.
+    fn update(&mut self, _cycles: usize) {}
+}
+
+// Serial holds the SB (0xFF01) and SC (0xFF02) registers along with the
+// device plugged into the link port, if any.
+pub struct Serial {
+    pub data: u8,
+    pub control: u8,
+    // The cycles left until the byte being transferred has been shifted.
+    cycles: usize,
+    device: Option<Box<dyn SerialDevice>>,
+}
+
+impl Serial {
+    pub fn new() -> Serial {
+        return Serial {
+            data: 0,
+            control: 0x7E,
+            cycles: 0,
+            device: None,
+        };
+    }
+
+    // transferring returns whether a transfer has been started through SC.
+    pub fn transferring(&self) -> bool {
+        return test(self.control, 7);
+    }
+
+    fn internal_clock(&self) -> bool {
+        return test(self.control, 0);
+    }
+
+    pub fn write_control(&mut self, value: u8, cgb_mode: bool) {
+        self.control = value;
+        if !self.transferring() {
+            return;
+        }
+        if self.internal_clock() {
+            let bit_cycles = if cgb_mode && test(value, 1) { FAST_BIT_CYCLES } else { BIT_CYCLES };
+            self.cycles = bit_cycles * 8;
+        } else {
+            // Let the device know straight away that a byte is ready.
+            self.cycles = 0;
+        }
+    }
+
+    // finish ends the transfer with the byte shifted in, returning true as
+    // the serial interrupt should be requested.
+    fn finish(&mut self, byte: u8) -> bool {
+        self.data = byte;
+        self.control = reset(self.control, 7);
+        return true;
+    }
+
+    // tick advances the transfer in progress, returning true if the serial
+    // interrupt should be requested.
+    pub fn tick(&mut self, cycles: usize) -> bool {
+        if let Some(device) = self.device.as_mut() {
+            device.update(cycles);
+        }
+        if !self.transferring() {
+            return false;
+        }
+        if self.cycles > cycles {
+            self.cycles -= cycles;
+            return false;
+        }
+
+        if self.internal_clock() {
+            // With nothing plugged in, the data line is pulled high.
+            let byte = match self.device.as_mut() {
+                Some(device) => device.transfer(self.data),
+                None => 0xFF,
+            };
+            return self.finish(byte);
+        }
+
+        // With an external clock the transfer only ends once the device
+        // sends something. It is checked once per byte time to keep the
+        // device from being polled every cycle.
+        self.cycles = BIT_CYCLES * 8;
+        let received = match self.device.as_mut() {
+            Some(device) => device.external_transfer(self.data),
+            None => None,
+        };
+        return match received {
+            Some(byte) => self.finish(byte),
+            None => false,
+        };
+    }
+}
+
+impl Gameboy {
+    // connect_serial plugs a device into the link port, replacing whatever
+    // was plugged in before.
+    pub fn connect_serial(&mut self, device: Box<dyn SerialDevice>) {
+        self.serial.device = Some(device);
+    }
+
+    // disconnect_serial unplugs whatever is plugged into the link port.
+    pub fn disconnect_serial(&mut self) {
+        self.serial.device = None;
+    }
+
+    pub fn update_serial(&mut self, cycles: usize) {
+        if self.serial.tick(cycles) {
+            self.request_interrupt(SERIAL);
+        }
+    }
+}
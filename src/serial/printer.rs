@@ -0,0 +1,495 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use crate::memory::CLOCK_SPEED;
+use crate::serial::SerialDevice;
+
+const INIT: u8 = 0x01;
+const PRINT: u8 = 0x02;
+const DATA: u8 = 0x04;
+const STATUS: u8 = 0x0F;
+
+// The printer answers the byte after a packet's checksum with ALIVE, and the
+// one after that with its status.
+const ALIVE: u8 = 0x81;
+const STATUS_CHECKSUM_ERROR: u8 = 0x01;
+const STATUS_PRINTING: u8 = 0x02;
+const STATUS_FULL: u8 = 0x04;
+const STATUS_UNPROCESSED: u8 = 0x08;
+
+// WIDTH is the width of the paper in pixels, 20 tiles.
+const WIDTH: usize = 160;
+// BUFFER_SIZE is the printer's memory, enough for 9 DATA packets of 2 rows
+// of tiles each.
+const BUFFER_SIZE: usize = 0x1680;
+// MARGIN_LINES is how far the paper is fed for each unit of margin.
+const MARGIN_LINES: usize = 8;
+// PRINTING_POLLS is the number of STATUS packets the printer reports itself
+// busy for after printing, as games wait to see printing start and stop.
+const PRINTING_POLLS: u8 = 4;
+// IDLE_CYCLES is how long the printer waits for another packet before saving
+// a page that hasn't been fed out, about 2 seconds.
+const IDLE_CYCLES: usize = 2 * CLOCK_SPEED;
+// SHADES are the grey levels of the 4 shades the printer can print.
+const SHADES: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+
+// PacketState is the part of a packet the printer expects next. Each packet
+// is the magic bytes 0x88 0x33, a command, a compression flag, a 16 bit
+// length, that much data, a 16 bit checksum and two bytes for the printer
+// to answer with.
+#[derive(Clone, Copy, PartialEq)]
+enum PacketState {
+    Magic1,
+    Magic2,
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    Alive,
+    Status,
+}
+
+// Printer emulates the Game Boy Printer. Each printed strip is added to the
+// current page, and a page is saved as a PNG in the output directory once a
+// print ends with a margin, which feeds the paper out. Games that print
+// without one have the page saved once they send INIT or stop printing for
+// IDLE_CYCLES, or when the printer is unplugged.
+pub struct Printer {
+    directory: PathBuf,
+    state: PacketState,
+    command: u8,
+    compressed: bool,
+    length: usize,
+    data: Vec<u8>,
+    checksum: u16,
+    received_checksum: u16,
+    status: u8,
+    printing_polls: u8,
+    // Tile data received through DATA packets, waiting to be printed.
+    buffer: Vec<u8>,
+    // The shades of the page printed so far, a row of WIDTH at a time.
+    page: Vec<u8>,
+    pages_saved: usize,
+    // The cycles since the last byte was received.
+    idle_cycles: usize,
+}
+
+impl Printer {
+    pub fn new(directory: PathBuf) -> Printer {
+        return Printer {
+            directory,
+            state: PacketState::Magic1,
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: Vec::new(),
+            checksum: 0,
+            received_checksum: 0,
+            status: 0,
+            printing_polls: 0,
+            buffer: Vec::with_capacity(BUFFER_SIZE),
+            page: Vec::new(),
+            pages_saved: 0,
+            idle_cycles: 0,
+        };
+    }
+
+    fn add_to_checksum(&mut self, byte: u8) {
+        self.checksum = self.checksum.wrapping_add(byte as u16);
+    }
+
+    fn handle_packet(&mut self) {
+        if self.checksum != self.received_checksum {
+            self.status |= STATUS_CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !STATUS_CHECKSUM_ERROR;
+
+        match self.command {
+            INIT => {
+                self.save_page();
+                self.buffer.clear();
+                self.status = 0;
+                self.printing_polls = 0;
+            }
+            DATA => {
+                // An empty DATA packet only marks the end of the image.
+                if self.data.is_empty() {
+                    return;
+                }
+                let data = if self.compressed { decompress(&self.data) } else { self.data.clone() };
+                let space = BUFFER_SIZE - self.buffer.len();
+                self.buffer.extend(data.into_iter().take(space));
+                self.status |= STATUS_UNPROCESSED;
+                // Anything past the end of the buffer is dropped.
+                if self.buffer.len() == BUFFER_SIZE {
+                    self.status |= STATUS_FULL;
+                }
+            }
+            PRINT => {
+                if self.data.len() < 4 {
+                    return;
+                }
+                let sheets = self.data[0];
+                let margins = self.data[1];
+                let palette = if self.data[2] == 0 { 0xE4 } else { self.data[2] };
+
+                self.feed((margins >> 4) as usize);
+                if sheets > 0 {
+                    self.print(palette);
+                }
+                self.feed((margins & 0xF) as usize);
+                if margins & 0xF > 0 {
+                    self.save_page();
+                }
+
+                self.buffer.clear();
+                self.status = (self.status & !(STATUS_UNPROCESSED | STATUS_FULL)) | STATUS_PRINTING;
+                self.printing_polls = PRINTING_POLLS;
+            }
+            STATUS => {
+                if self.printing_polls > 0 {
+                    self.printing_polls -= 1;
+                    if self.printing_polls == 0 {
+                        self.status &= !STATUS_PRINTING;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // feed adds blank paper for the given margin.
+    fn feed(&mut self, margin: usize) {
+        let len = self.page.len() + margin * MARGIN_LINES * WIDTH;
+        self.page.resize(len, SHADES[0]);
+    }
+
+    // print adds the buffered tiles to the page, 20 tiles to a row.
+    fn print(&mut self, palette: u8) {
+        let rows = self.buffer.len() / (16 * WIDTH / 8);
+        for y in 0..(rows * 8) {
+            for x in 0..WIDTH {
+                let tile = (y / 8) * (WIDTH / 8) + x / 8;
+                let address = tile * 16 + (y % 8) * 2;
+                let bit = 7 - (x % 8);
+                let low = (self.buffer[address] >> bit) & 1;
+                let high = (self.buffer[address + 1] >> bit) & 1;
+                let color = (high << 1) | low;
+                let shade = (palette >> (color * 2)) & 0x3;
+                self.page.push(SHADES[shade as usize]);
+            }
+        }
+    }
+
+    // save_page writes the page printed so far as a PNG and starts a new one,
+    // if anything has been printed.
+    fn save_page(&mut self) {
+        let height = self.page.len() / WIDTH;
+        if height > 0 {
+            let path = self.next_path();
+            match self.write_png(&path, height) {
+                Ok(()) => println!("Printed {}", path.display()),
+                Err(e) => println!("Unable to save print to {}: {}", path.display(), e),
+            }
+        }
+        self.page.clear();
+    }
+
+    fn next_path(&mut self) -> PathBuf {
+        loop {
+            self.pages_saved += 1;
+            let path = self.directory.join(format!("print-{:04}.png", self.pages_saved));
+            if !path.exists() {
+                return path;
+            }
+        }
+    }
+
+    fn write_png(&self, path: &PathBuf, height: usize) -> Result<(), png::EncodingError> {
+        let file = File::create(path)?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), WIDTH as u32, height as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.page)?;
+        return Ok(());
+    }
+}
+
+// decompress expands the run length encoding used by DATA packets. Each run
+// starts with a byte; with bit 7 set the next byte is repeated (n & 0x7F) + 2
+// times, otherwise the next n + 1 bytes are copied as they are.
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let control = data[i] as usize;
+        i += 1;
+        if control & 0x80 != 0 {
+            if i < data.len() {
+                let len = (control & 0x7F) + 2;
+                output.resize(output.len() + len, data[i]);
+                i += 1;
+            }
+        } else {
+            let end = (i + control + 1).min(data.len());
+            output.extend_from_slice(&data[i..end]);
+            i = end;
+        }
+    }
+    return output;
+}
+
+impl SerialDevice for Printer {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        self.idle_cycles = 0;
+        match self.state {
+            PacketState::Magic1 => {
+                if byte == 0x88 {
+                    self.state = PacketState::Magic2;
+                }
+            }
+            PacketState::Magic2 => {
+                self.state = match byte {
+                    0x33 => PacketState::Command,
+                    0x88 => PacketState::Magic2,
+                    _ => PacketState::Magic1,
+                };
+            }
+            PacketState::Command => {
+                self.command = byte;
+                self.checksum = 0;
+                self.add_to_checksum(byte);
+                self.state = PacketState::Compression;
+            }
+            PacketState::Compression => {
+                self.compressed = byte & 0x1 != 0;
+                self.add_to_checksum(byte);
+                self.state = PacketState::LengthLow;
+            }
+            PacketState::LengthLow => {
+                self.length = byte as usize;
+                self.add_to_checksum(byte);
+                self.state = PacketState::LengthHigh;
+            }
+            PacketState::LengthHigh => {
+                self.length |= (byte as usize) << 8;
+                self.add_to_checksum(byte);
+                self.data.clear();
+                self.state = if self.length > 0 { PacketState::Data } else { PacketState::ChecksumLow };
+            }
+            PacketState::Data => {
+                self.data.push(byte);
+                self.add_to_checksum(byte);
+                if self.data.len() == self.length {
+                    self.state = PacketState::ChecksumLow;
+                }
+            }
+            PacketState::ChecksumLow => {
+                self.received_checksum = byte as u16;
+                self.state = PacketState::ChecksumHigh;
+            }
+            PacketState::ChecksumHigh => {
+                self.received_checksum |= (byte as u16) << 8;
+                self.state = PacketState::Alive;
+            }
+            PacketState::Alive => {
+                self.handle_packet();
+                self.state = PacketState::Status;
+                return ALIVE;
+            }
+            PacketState::Status => {
+                self.state = PacketState::Magic1;
+                return self.status;
+            }
+        }
+        return 0x00;
+    }
+
+    fn external_transfer(&mut self, _byte: u8) -> Option<u8> {
+        // The printer never provides the clock.
+        return None;
+    }
+
+    fn update(&mut self, cycles: usize) {
+        if self.page.is_empty() {
+            return;
+        }
+        self.idle_cycles += cycles;
+        if self.idle_cycles >= IDLE_CYCLES {
+            self.save_page();
+        }
+    }
+}
+
+impl Drop for Printer {
+    fn drop(&mut self) {
+        self.save_page();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::serial::printer::{decompress, Printer, BUFFER_SIZE, DATA, IDLE_CYCLES, INIT, PRINT, STATUS, WIDTH};
+    use crate::serial::printer::{STATUS_CHECKSUM_ERROR, STATUS_FULL, STATUS_PRINTING, STATUS_UNPROCESSED};
+    use crate::serial::SerialDevice;
+    use std::fs;
+    use std::path::PathBuf;
+
+    // ROW_BYTES is the size of the tile data for one row of tiles.
+    const ROW_BYTES: usize = 16 * WIDTH / 8;
+
+    // printer returns a printer saving to a new directory named after the
+    // test.
+    fn printer(test: &str) -> (Printer, PathBuf) {
+        let directory = std::env::temp_dir().join(format!("gameboy-printer-{}-{}", std::process::id(), test));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir(&directory).unwrap();
+        return (Printer::new(directory.clone()), directory);
+    }
+
+    // saved returns the number of pages saved in a directory.
+    fn saved(directory: &PathBuf) -> usize {
+        return fs::read_dir(directory).unwrap().count();
+    }
+
+    // clean_up unplugs the printer, then removes its directory.
+    fn clean_up(printer: Printer, directory: &PathBuf) {
+        drop(printer);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    // print_strip prints one row of tiles without feeding the paper out.
+    fn print_strip(printer: &mut Printer) {
+        send(printer, DATA, &vec![0xFF; ROW_BYTES]);
+        send(printer, PRINT, &[0x01, 0x00, 0xE4, 0x40]);
+    }
+
+    // send sends a packet with the given checksum, returning the status the
+    // printer answers with.
+    fn send_with_checksum(printer: &mut Printer, command: u8, data: &[u8], checksum: u16) -> u8 {
+        let mut packet = vec![0x88, 0x33, command, 0x00, data.len() as u8, (data.len() >> 8) as u8];
+        packet.extend_from_slice(data);
+        packet.extend_from_slice(&[checksum as u8, (checksum >> 8) as u8, 0x00, 0x00]);
+        let answers: Vec<u8> = packet.into_iter().map(|byte| printer.transfer(byte)).collect();
+        assert_eq!(answers[answers.len() - 2], 0x81);
+        return answers[answers.len() - 1];
+    }
+
+    fn send(printer: &mut Printer, command: u8, data: &[u8]) -> u8 {
+        let checksum = [command, 0x00, data.len() as u8, (data.len() >> 8) as u8].iter()
+            .chain(data.iter())
+            .fold(0_u16, |sum, &byte| sum.wrapping_add(byte as u16));
+        return send_with_checksum(printer, command, data, checksum);
+    }
+
+    #[test]
+    fn decompresses_runs_and_literals() {
+        // A run of 3 0xAA, 2 literal bytes and a run of 2 0x00.
+        let data = [0x81, 0xAA, 0x01, 0x11, 0x22, 0x80, 0x00];
+        assert_eq!(decompress(&data), vec![0xAA, 0xAA, 0xAA, 0x11, 0x22, 0x00, 0x00]);
+        // Literals cut off by the end of the data are kept, runs are dropped.
+        assert_eq!(decompress(&[0x02, 0x11, 0x22]), vec![0x11, 0x22]);
+        assert_eq!(decompress(&[0x00, 0x11, 0x85]), vec![0x11]);
+    }
+
+    #[test]
+    fn reports_checksum_errors() {
+        let (mut printer, directory) = printer("reports_checksum_errors");
+        assert_eq!(send_with_checksum(&mut printer, INIT, &[], 0x1234) & STATUS_CHECKSUM_ERROR, STATUS_CHECKSUM_ERROR);
+        assert_eq!(send(&mut printer, INIT, &[]), 0x00);
+        clean_up(printer, &directory);
+    }
+
+    #[test]
+    fn prints_with_palette_and_margins() {
+        let (mut printer, directory) = printer("prints_with_palette_and_margins");
+        send(&mut printer, INIT, &[]);
+        // Every row of every tile is colors 3 3 3 3 2 2 2 2.
+        let tiles: Vec<u8> = [0xF0, 0xFF].iter().cloned().cycle().take(ROW_BYTES * 2).collect();
+        assert_eq!(send(&mut printer, DATA, &tiles), STATUS_UNPROCESSED);
+        send(&mut printer, DATA, &[]);
+
+        // 1 sheet, a margin of 1 before and none after, and an inverted
+        // palette.
+        let status = send(&mut printer, PRINT, &[0x01, 0x10, 0x1B, 0x40]);
+        assert_eq!(status, STATUS_PRINTING);
+        let margin = 8 * WIDTH;
+        assert_eq!(printer.page.len(), margin + 16 * WIDTH);
+        assert!(printer.page[..margin].iter().all(|&shade| shade == 0xFF));
+        for row in printer.page[margin..].chunks(WIDTH) {
+            assert_eq!(row[..8], [0xFF, 0xFF, 0xFF, 0xFF, 0xAA, 0xAA, 0xAA, 0xAA]);
+        }
+        clean_up(printer, &directory);
+    }
+
+    #[test]
+    fn stops_printing_after_polls() {
+        let (mut printer, directory) = printer("stops_printing_after_polls");
+        send(&mut printer, DATA, &vec![0; ROW_BYTES * 2]);
+        send(&mut printer, PRINT, &[0x01, 0x00, 0xE4, 0x40]);
+        for _ in 0..3 {
+            assert_eq!(send(&mut printer, STATUS, &[]), STATUS_PRINTING);
+        }
+        assert_eq!(send(&mut printer, STATUS, &[]), 0x00);
+        clean_up(printer, &directory);
+    }
+
+    #[test]
+    fn reports_full_buffer() {
+        let (mut printer, directory) = printer("reports_full_buffer");
+        let tiles = vec![0; ROW_BYTES * 2];
+        for _ in 0..8 {
+            assert_eq!(send(&mut printer, DATA, &tiles) & STATUS_FULL, 0);
+        }
+        // The 9th packet fills the buffer, and the 10th doesn't fit.
+        assert_eq!(send(&mut printer, DATA, &tiles) & STATUS_FULL, STATUS_FULL);
+        assert_eq!(send(&mut printer, DATA, &tiles) & STATUS_FULL, STATUS_FULL);
+        assert_eq!(printer.buffer.len(), BUFFER_SIZE);
+
+        assert_eq!(send(&mut printer, INIT, &[]), 0x00);
+        clean_up(printer, &directory);
+    }
+
+    #[test]
+    fn saves_unfed_pages_on_init() {
+        let (mut printer, directory) = printer("saves_unfed_pages_on_init");
+        print_strip(&mut printer);
+        print_strip(&mut printer);
+        assert_eq!(saved(&directory), 0);
+        send(&mut printer, INIT, &[]);
+        assert_eq!(saved(&directory), 1);
+        assert!(printer.page.is_empty());
+        clean_up(printer, &directory);
+    }
+
+    #[test]
+    fn saves_unfed_pages_once_idle() {
+        let (mut printer, directory) = printer("saves_unfed_pages_once_idle");
+        print_strip(&mut printer);
+        printer.update(IDLE_CYCLES - 4);
+        // Polling the status keeps the page open.
+        send(&mut printer, STATUS, &[]);
+        printer.update(IDLE_CYCLES - 4);
+        assert_eq!(saved(&directory), 0);
+        printer.update(4);
+        assert_eq!(saved(&directory), 1);
+        // Nothing more is saved until something else is printed.
+        printer.update(IDLE_CYCLES);
+        assert_eq!(saved(&directory), 1);
+        clean_up(printer, &directory);
+    }
+
+    #[test]
+    fn saves_unfed_pages_when_unplugged() {
+        let (mut printer, directory) = printer("saves_unfed_pages_when_unplugged");
+        print_strip(&mut printer);
+        drop(printer);
+        assert_eq!(saved(&directory), 1);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
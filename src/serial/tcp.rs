@@ -0,0 +1,185 @@
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::serial::SerialDevice;
+
+// Every message is a tag, the number of the transfer it belongs to and a
+// byte. READY tells the other end which byte this end will send when the
+// other end clocks a transfer, and DATA is a byte transferred with this end's
+// clock in answer to a READY. The numbers let each end ignore messages left
+// over from an earlier transfer.
+const READY: u8 = 0;
+const DATA: u8 = 1;
+const MESSAGE_LEN: usize = 3;
+
+// READY_TIMEOUT is how long a transfer waits for the other end to say which
+// byte it is sending. If it hasn't by then, it isn't waiting for a transfer.
+const READY_TIMEOUT: Duration = Duration::from_millis(20);
+
+// TcpLink is a link cable to another emulator over TCP. One emulator listens
+// for the other to connect, after which both ends behave the same.
+pub struct TcpLink {
+    // Set until the other emulator has connected to a listening link.
+    listener: Option<TcpListener>,
+    stream: Option<TcpStream>,
+    buffer: Vec<u8>,
+    // The number of transfers the other end has clocked for this one.
+    transfers: u8,
+    // The transfer and byte last announced to the other end with READY.
+    announced: Option<(u8, u8)>,
+    // The transfer and byte the other end has ready to send.
+    ready: Option<(u8, u8)>,
+    // The transfer this end last clocked, whose READY is used up.
+    clocked: Option<u8>,
+    // Bytes the other end has transferred using its own clock.
+    received: VecDeque<(u8, u8)>,
+}
+
+impl TcpLink {
+    // listen listens for another emulator to connect on the given address.
+    // The link behaves as if nothing was plugged in until it does.
+    pub fn listen<A: ToSocketAddrs>(addr: A) -> io::Result<TcpLink> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let mut link = TcpLink::new(None);
+        link.listener = Some(listener);
+        return Ok(link);
+    }
+
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<TcpLink> {
+        let stream = TcpStream::connect(addr)?;
+        TcpLink::configure(&stream)?;
+        return Ok(TcpLink::new(Some(stream)));
+    }
+
+    fn new(stream: Option<TcpStream>) -> TcpLink {
+        return TcpLink {
+            listener: None,
+            stream,
+            buffer: Vec::new(),
+            transfers: 0,
+            announced: None,
+            ready: None,
+            clocked: None,
+            received: VecDeque::new(),
+        };
+    }
+
+    fn configure(stream: &TcpStream) -> io::Result<()> {
+        stream.set_nonblocking(true)?;
+        return stream.set_nodelay(true);
+    }
+
+    // accept takes the connection of the other emulator to a listening link,
+    // if it has connected.
+    fn accept(&mut self) {
+        let accepted = match self.listener.as_ref() {
+            Some(listener) => listener.accept(),
+            None => return,
+        };
+        match accepted {
+            Ok((stream, addr)) => {
+                if TcpLink::configure(&stream).is_ok() {
+                    println!("Link cable connected to {}", addr);
+                    self.stream = Some(stream);
+                    self.listener = None;
+                }
+            }
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) => {
+                println!("Unable to accept link cable connection: {}", e);
+                self.listener = None;
+            }
+        }
+    }
+
+    fn send(&mut self, tag: u8, transfer: u8, byte: u8) {
+        if let Some(stream) = self.stream.as_mut() {
+            if stream.write_all(&[tag, transfer, byte]).is_err() {
+                self.disconnect();
+            }
+        }
+    }
+
+    // poll reads every message that has arrived so far.
+    fn poll(&mut self) {
+        if self.stream.is_none() {
+            self.accept();
+        }
+        let mut chunk = [0; 64];
+        while let Some(stream) = self.stream.as_mut() {
+            match stream.read(&mut chunk) {
+                Ok(0) => self.disconnect(),
+                Ok(len) => self.buffer.extend_from_slice(&chunk[..len]),
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(_) => self.disconnect(),
+            }
+        }
+
+        let messages = self.buffer.len() / MESSAGE_LEN;
+        for message in self.buffer[..messages * MESSAGE_LEN].chunks(MESSAGE_LEN) {
+            let (transfer, byte) = (message[1], message[2]);
+            match message[0] {
+                READY if self.clocked != Some(transfer) => self.ready = Some((transfer, byte)),
+                DATA => self.received.push_back((transfer, byte)),
+                _ => {}
+            }
+        }
+        self.buffer.drain(..messages * MESSAGE_LEN);
+    }
+
+    // wait_ready polls until the other end has said which byte it is sending
+    // in the next transfer, or READY_TIMEOUT has passed.
+    fn wait_ready(&mut self) {
+        let start = Instant::now();
+        self.poll();
+        while self.ready.is_none() && self.stream.is_some() && start.elapsed() < READY_TIMEOUT {
+            thread::sleep(Duration::from_millis(1));
+            self.poll();
+        }
+    }
+
+    // disconnect drops the connection. The cable then behaves as if nothing
+    // was plugged in.
+    fn disconnect(&mut self) {
+        if self.stream.take().is_some() {
+            println!("Link cable disconnected");
+        }
+        self.ready = None;
+    }
+}
+
+impl SerialDevice for TcpLink {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        self.wait_ready();
+        return match self.ready.take() {
+            Some((transfer, ready)) => {
+                self.clocked = Some(transfer);
+                self.send(DATA, transfer, byte);
+                ready
+            }
+            // Nothing is shifted in if the other end isn't waiting.
+            None => 0xFF,
+        };
+    }
+
+    fn external_transfer(&mut self, byte: u8) -> Option<u8> {
+        self.poll();
+        // Only a transfer answering the latest READY counts, an earlier one
+        // was clocked while this end wasn't waiting for it.
+        while let Some((transfer, received)) = self.received.pop_front() {
+            if transfer == self.transfers {
+                self.transfers = self.transfers.wrapping_add(1);
+                return Some(received);
+            }
+        }
+        if self.announced != Some((self.transfers, byte)) {
+            self.announced = Some((self.transfers, byte));
+            self.send(READY, self.transfers, byte);
+        }
+        return None;
+    }
+}
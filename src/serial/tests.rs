@@ -0,0 +1,158 @@
+use crate::cart::controller::Cart;
+use crate::gameboy::Gameboy;
+use crate::interrupts::SERIAL;
+use crate::bit_functions::test;
+use crate::serial::link::LinkCable;
+use crate::serial::tcp::TcpLink;
+use std::net::TcpListener;
+use std::thread;
+use std::time::Duration;
+use crate::serial::BIT_CYCLES;
+
+fn gameboy() -> Gameboy {
+    return Gameboy::from_cart(Cart::from_rom(vec![0; 0x8000], "").unwrap());
+}
+
+fn linked() -> (Gameboy, Gameboy) {
+    let (first, second) = LinkCable::pair();
+    let mut a = gameboy();
+    let mut b = gameboy();
+    a.connect_serial(Box::new(first));
+    b.connect_serial(Box::new(second));
+    return (a, b);
+}
+
+// exchange has the master send a byte using its internal clock while the
+// slave waits with a byte on the external clock.
+fn exchange(master: &mut Gameboy, slave: &mut Gameboy, master_byte: u8, slave_byte: u8) {
+    master.interrupts.requested = 0;
+    slave.interrupts.requested = 0;
+    slave.write(0xFF01, slave_byte);
+    slave.write(0xFF02, 0x80);
+    // The slave has to have looked for a transfer once for the cable to know
+    // what it is sending.
+    slave.update_serial(4);
+    master.write(0xFF01, master_byte);
+    master.write(0xFF02, 0x81);
+
+    master.update_serial(BIT_CYCLES * 8);
+    assert_eq!(master.read(0xFF01), slave_byte);
+    assert!(!test(master.read(0xFF02), 7));
+    assert!(test(master.interrupts.requested, SERIAL));
+
+    // The slave only sees the transfer when it next polls the cable.
+    assert!(test(slave.read(0xFF02), 7));
+    slave.update_serial(BIT_CYCLES * 8);
+    assert_eq!(slave.read(0xFF01), master_byte);
+    assert!(!test(slave.read(0xFF02), 7));
+    assert!(test(slave.interrupts.requested, SERIAL));
+}
+
+#[test]
+fn link_cable_exchanges_bytes_both_ways() {
+    let (mut a, mut b) = linked();
+    exchange(&mut a, &mut b, 0x42, 0x99);
+    // Swap which one provides the clock.
+    exchange(&mut b, &mut a, 0x17, 0x71);
+}
+
+#[test]
+fn internal_clock_without_cable_reads_ones() {
+    let mut gb = gameboy();
+    gb.write(0xFF01, 0x42);
+    gb.write(0xFF02, 0x81);
+    gb.update_serial(BIT_CYCLES * 8 - 4);
+    assert_eq!(gb.read(0xFF01), 0x42);
+    gb.update_serial(4);
+    assert_eq!(gb.read(0xFF01), 0xFF);
+}
+
+#[test]
+fn external_clock_without_cable_never_finishes() {
+    let mut gb = gameboy();
+    gb.write(0xFF02, 0x80);
+    gb.update_serial(BIT_CYCLES * 80);
+    assert!(test(gb.read(0xFF02), 7));
+}
+
+// tcp_linked returns two Gameboys linked over TCP, the first having
+// connected to the second.
+fn tcp_linked() -> (Gameboy, Gameboy) {
+    // Find a free port, then have one end listen on it for the other.
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let listening = TcpLink::listen(("127.0.0.1", port)).unwrap();
+    let connected = TcpLink::connect(("127.0.0.1", port)).unwrap();
+    let mut a = gameboy();
+    let mut b = gameboy();
+    a.connect_serial(Box::new(connected));
+    b.connect_serial(Box::new(listening));
+    return (a, b);
+}
+
+// wait_external has a Gameboy wait for the other end to clock a transfer,
+// returning the byte it received.
+fn wait_external(gb: &mut Gameboy, byte: u8) -> u8 {
+    gb.write(0xFF01, byte);
+    gb.write(0xFF02, 0x80);
+    for _ in 0..100 {
+        gb.update_serial(BIT_CYCLES * 8);
+        if !test(gb.read(0xFF02), 7) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    return gb.read(0xFF01);
+}
+
+fn clock(gb: &mut Gameboy, byte: u8) -> u8 {
+    gb.write(0xFF01, byte);
+    gb.write(0xFF02, 0x81);
+    gb.update_serial(BIT_CYCLES * 8);
+    return gb.read(0xFF01);
+}
+
+#[test]
+fn tcp_link_exchanges_bytes() {
+    let (mut master, mut slave) = tcp_linked();
+    slave.write(0xFF01, 0x99);
+    slave.write(0xFF02, 0x80);
+    // The listening end takes the connection the first time it is used.
+    slave.update_serial(4);
+    assert_eq!(clock(&mut master, 0x42), 0x99);
+    assert_eq!(wait_external(&mut slave, 0x99), 0x42);
+}
+
+#[test]
+fn tcp_link_ignores_earlier_transfers() {
+    let (mut master, mut slave) = tcp_linked();
+    slave.write(0xFF01, 0x11);
+    slave.write(0xFF02, 0x80);
+    slave.update_serial(4);
+    // The slave changes its mind before the master clocks.
+    slave.write(0xFF01, 0x22);
+    slave.update_serial(BIT_CYCLES * 8);
+    assert_eq!(clock(&mut master, 0x42), 0x22);
+    assert_eq!(wait_external(&mut slave, 0x22), 0x42);
+
+    // The slave isn't waiting, so its earlier bytes aren't sent again.
+    assert_eq!(clock(&mut master, 0x43), 0xFF);
+    slave.write(0xFF01, 0x33);
+    slave.write(0xFF02, 0x80);
+    slave.update_serial(4);
+    assert_eq!(clock(&mut master, 0x44), 0x33);
+    assert_eq!(wait_external(&mut slave, 0x33), 0x44);
+}
+
+#[test]
+fn capture_records_sent_bytes() {
+    let mut gb = gameboy();
+    let output = gb.capture_serial();
+    for &byte in b"ok".iter() {
+        gb.write(0xFF01, byte);
+        gb.write(0xFF02, 0x81);
+        gb.update_serial(BIT_CYCLES * 8);
+        // Nothing is sent back.
+        assert_eq!(gb.read(0xFF01), 0xFF);
+    }
+    assert_eq!(output.text(), "ok");
+}
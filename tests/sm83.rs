@@ -0,0 +1,149 @@
+// Runs the SM83 single step tests, which give the state before and after one
+// instruction along with the bus activity of every machine cycle. There is a
+// JSON file of tests for each of the 500 opcodes, named after the opcode like
+// "3e.json" or "cb 3e.json". They are read from the directory named by
+// SM83_TESTS, or test-roms/sm83/v1 in the crate root. Like the test ROMs,
+// the suite is ignored by default and run with cargo test -- --ignored.
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde_json::Value;
+use gameboy_emulator::cpu::test_bus::{BusCycle, TestBus};
+use gameboy_emulator::gameboy::Gameboy;
+
+// The number of opcodes, leaving out the 11 that lock up the CPU and the CB
+// prefix itself.
+const OPCODES: usize = 500;
+
+fn number(state: &Value, name: &str) -> u16 {
+    return state[name].as_u64().unwrap_or_else(|| panic!("Missing {}", name)) as u16;
+}
+
+fn set_state(gb: &mut Gameboy, bus: &TestBus, state: &Value) {
+    gb.cpu.af.set_full(number(state, "a") << 8 | number(state, "f"));
+    gb.cpu.bc.set_full(number(state, "b") << 8 | number(state, "c"));
+    gb.cpu.de.set_full(number(state, "d") << 8 | number(state, "e"));
+    gb.cpu.hl.set_full(number(state, "h") << 8 | number(state, "l"));
+    gb.cpu.pc = number(state, "pc");
+    gb.cpu.sp.set_full(number(state, "sp"));
+    if number(state, "ime") == 1 {
+        gb.interrupts.enable();
+    } else {
+        gb.interrupts.disable();
+    }
+    gb.interrupts.enabled = number(state, "ie") as u8;
+
+    for entry in state["ram"].as_array().unwrap() {
+        let addr = entry[0].as_u64().unwrap() as u16;
+        bus.set(addr, entry[1].as_u64().unwrap() as u8);
+    }
+}
+
+// compare_state returns the differences between the Gameboy and a state.
+fn compare_state(gb: &Gameboy, bus: &TestBus, state: &Value) -> Vec<String> {
+    let registers = [
+        ("a", gb.cpu.af.hi() as u16), ("f", gb.cpu.af.lo() as u16),
+        ("b", gb.cpu.bc.hi() as u16), ("c", gb.cpu.bc.lo() as u16),
+        ("d", gb.cpu.de.hi() as u16), ("e", gb.cpu.de.lo() as u16),
+        ("h", gb.cpu.hl.hi() as u16), ("l", gb.cpu.hl.lo() as u16),
+        ("pc", gb.cpu.pc), ("sp", gb.cpu.sp.full()),
+        ("ime", gb.interrupts.ime as u16),
+    ];
+    let mut differences = Vec::new();
+    for (name, value) in registers.iter() {
+        let expected = number(state, name);
+        if *value != expected {
+            differences.push(format!("{} is {:#X}, expected {:#X}", name, value, expected));
+        }
+    }
+
+    for entry in state["ram"].as_array().unwrap() {
+        let addr = entry[0].as_u64().unwrap() as u16;
+        let expected = entry[1].as_u64().unwrap() as u8;
+        if bus.get(addr) != expected {
+            differences.push(format!("({:#06X}) is {:#04X}, expected {:#04X}", addr, bus.get(addr), expected));
+        }
+    }
+    return differences;
+}
+
+// parse_cycle reads a cycle of the test, an address, a value and pins showing
+// whether it read ("r-m"), wrote ("-wm") or did neither. Cycles without a
+// memory access may also be null.
+fn parse_cycle(cycle: &Value) -> BusCycle {
+    let pins = cycle[2].as_str().unwrap_or("---");
+    let addr = cycle[0].as_u64().unwrap_or(0) as u16;
+    let value = cycle[1].as_u64().unwrap_or(0) as u8;
+    if pins.contains('r') {
+        return BusCycle::Read(addr, value);
+    }
+    if pins.contains('w') {
+        return BusCycle::Write(addr, value);
+    }
+    return BusCycle::Internal;
+}
+
+// run_test runs a single test, returning why it failed.
+fn run_test(test: &Value) -> Result<(), String> {
+    let (mut gb, bus) = Gameboy::with_test_bus();
+    set_state(&mut gb, &bus, &test["initial"]);
+    gb.execute_next_opcode();
+
+    let differences = compare_state(&gb, &bus, &test["final"]);
+    if !differences.is_empty() {
+        return Err(differences.join(", "));
+    }
+    let expected: Vec<BusCycle> = test["cycles"].as_array().unwrap().iter().map(parse_cycle).collect();
+    let cycles = bus.cycles();
+    if cycles != expected {
+        return Err(format!("cycles {:X?}, expected {:X?}", cycles, expected));
+    }
+    return Ok(());
+}
+
+fn test_directory() -> PathBuf {
+    return match env::var_os("SM83_TESTS") {
+        Some(directory) => PathBuf::from(directory),
+        None => Path::new(env!("CARGO_MANIFEST_DIR")).join("test-roms/sm83/v1"),
+    };
+}
+
+#[test]
+#[ignore = "needs the SM83 tests, run with --ignored"]
+fn sm83_single_step() {
+    let directory = test_directory();
+    assert!(directory.is_dir(), "SM83 tests not found in {}, set SM83_TESTS", directory.display());
+    let mut files: Vec<PathBuf> = fs::read_dir(&directory).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |extension| extension == "json"))
+        .collect();
+    files.sort();
+
+    let mut failed = Vec::new();
+    for file in files.iter() {
+        let name = file.file_stem().unwrap().to_string_lossy().into_owned();
+        let text = fs::read_to_string(file).unwrap();
+        let tests: Value = serde_json::from_str(&text).unwrap();
+        let tests = tests.as_array().unwrap();
+
+        let mut failures = 0;
+        for test in tests.iter() {
+            if let Err(reason) = run_test(test) {
+                // Only the first failure of an opcode is shown, as the rest
+                // are usually the same.
+                if failures == 0 {
+                    println!("{}: {}", test["name"].as_str().unwrap_or(&name), reason);
+                }
+                failures += 1;
+            }
+        }
+        if failures > 0 {
+            println!("{}: {}/{} failed", name, failures, tests.len());
+            failed.push(name);
+        }
+    }
+
+    println!("{} opcodes tested, {} failed", files.len(), failed.len());
+    assert_eq!(files.len(), OPCODES, "Expected a test file for every opcode");
+    assert!(failed.is_empty(), "Opcodes failing: {:?}", failed);
+}
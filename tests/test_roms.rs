@@ -0,0 +1,360 @@
+// Conformance tests against the public test ROM suites. The ROMs are not part
+// of the repository; they are read from the directory named by GB_TEST_ROMS,
+// or test-roms/ in the crate root. The suite is ignored by default; run it with
+// cargo test --release -- --ignored, which fails if the ROMs can't be found.
+//
+// expected_results.txt lists every ROM with the result it is expected to
+// have, so a ROM expected to pass that fails or is missing fails the suite.
+// Running with GB_TEST_ROMS_BLESS=1 rewrites the file with the current
+// results.
+use std::env;
+use std::fs::{self, File};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use gameboy_emulator::gameboy::Gameboy;
+use gameboy_emulator::graphics::{PALETTE_GRAYSCALE, SCREEN_HEIGHT, SCREEN_WIDTH};
+use gameboy_emulator::memory::CLOCK_SPEED;
+
+const EXPECTED_RESULTS: &str = "tests/expected_results.txt";
+
+// The longest a ROM is run for, in emulated seconds. cpu_instrs takes close
+// to a minute on CGB.
+const BLARGG_SECONDS: usize = 120;
+const MOONEYE_SECONDS: usize = 20;
+const SCREENSHOT_SECONDS: usize = 20;
+
+// LD B,B is the breakpoint mooneye's, mealybug's and the acid2 ROMs execute
+// once they are done.
+const LD_B_B: u8 = 0x40;
+// Mooneye's ROMs leave the Fibonacci numbers in B, C, D, E, H and L to pass.
+const FIBONACCI: [u8; 6] = [3, 5, 8, 13, 21, 34];
+
+// Blargg's ROMs also report through cartridge RAM: a status byte at 0xA000
+// that stays RUNNING until the test is done, a signature and the output text.
+const BLARGG_STATUS: u16 = 0xA000;
+const BLARGG_RUNNING: u8 = 0x80;
+const BLARGG_SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const BLARGG_TEXT: u16 = 0xA004;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    // Reports over serial or cartridge RAM.
+    Blargg,
+    // Reports through registers at an LD B,B breakpoint.
+    Mooneye,
+    // Draws a screen to compare with a reference image at an LD B,B
+    // breakpoint, like the acid2 and mealybug ROMs.
+    Screenshot,
+}
+
+struct Entry {
+    kind: Kind,
+    rom: String,
+    expected: bool,
+    reference: Option<String>,
+}
+
+enum Outcome {
+    Pass,
+    Fail(String),
+    Missing,
+}
+
+fn parse_kind(kind: &str) -> Kind {
+    return match kind {
+        "blargg" => Kind::Blargg,
+        "mooneye" => Kind::Mooneye,
+        "screenshot" => Kind::Screenshot,
+        _ => panic!("Unknown test ROM kind {}", kind),
+    };
+}
+
+fn kind_name(kind: Kind) -> &'static str {
+    return match kind {
+        Kind::Blargg => "blargg",
+        Kind::Mooneye => "mooneye",
+        Kind::Screenshot => "screenshot",
+    };
+}
+
+// read_expected parses expected_results.txt. Each line is a kind, a ROM path,
+// pass or fail, and for screenshots the path of the reference image.
+fn read_expected(path: &Path) -> Vec<Entry> {
+    let text = fs::read_to_string(path).expect("Unable to read expected results");
+    let mut entries = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 3 {
+            panic!("Invalid expected result: {}", line);
+        }
+        entries.push(Entry {
+            kind: parse_kind(fields[0]),
+            rom: fields[1].to_string(),
+            expected: fields[2] == "pass",
+            reference: fields.get(3).map(|reference| reference.to_string()),
+        });
+    }
+    return entries;
+}
+
+// write_expected rewrites expected_results.txt with the given results,
+// keeping its comments and the order of the ROMs.
+fn write_expected(path: &Path, entries: &[Entry], results: &[bool]) {
+    let text = fs::read_to_string(path).expect("Unable to read expected results");
+    let mut output = String::new();
+    let mut results = results.iter();
+    let mut entries = entries.iter();
+    for line in text.lines() {
+        if line.trim().is_empty() || line.trim().starts_with('#') {
+            output.push_str(line);
+        } else {
+            let entry = entries.next().unwrap();
+            let passed = *results.next().unwrap();
+            output.push_str(&format!("{} {} {}", kind_name(entry.kind), entry.rom, if passed { "pass" } else { "fail" }));
+            if let Some(reference) = &entry.reference {
+                output.push_str(&format!(" {}", reference));
+            }
+        }
+        output.push('\n');
+    }
+    fs::write(path, output).expect("Unable to write expected results");
+}
+
+// run_to_breakpoint runs until an LD B,B has been executed.
+fn run_to_breakpoint(gb: &mut Gameboy, seconds: usize) -> bool {
+    let mut cycles = 0;
+    while cycles < seconds * CLOCK_SPEED {
+        let breakpoint = !gb.cpu.halted && gb.read(gb.cpu.pc) == LD_B_B;
+        cycles += gb.step();
+        if breakpoint {
+            return true;
+        }
+    }
+    return false;
+}
+
+// blargg_memory_result returns the result reported through cartridge RAM,
+// if the ROM reports there and has finished.
+fn blargg_memory_result(gb: &Gameboy) -> Option<Outcome> {
+    for (i, byte) in BLARGG_SIGNATURE.iter().enumerate() {
+        if gb.read(BLARGG_STATUS + 1 + i as u16) != *byte {
+            return None;
+        }
+    }
+    let status = gb.read(BLARGG_STATUS);
+    if status == BLARGG_RUNNING {
+        return None;
+    }
+    if status == 0 {
+        return Some(Outcome::Pass);
+    }
+
+    let mut text = String::new();
+    let mut addr = BLARGG_TEXT;
+    while addr < 0xC000 && gb.read(addr) != 0 {
+        text.push(gb.read(addr) as char);
+        addr += 1;
+    }
+    return Some(Outcome::Fail(format!("result {}: {}", status, text.trim())));
+}
+
+fn run_blargg(gb: &mut Gameboy) -> Outcome {
+    let output = gb.capture_serial();
+    let mut result = None;
+    let mut cycles = 0;
+    // Checking for a result every instruction would be slow, so it is only
+    // done once a frame.
+    while cycles < BLARGG_SECONDS * CLOCK_SPEED && result.is_none() {
+        cycles += gb.update();
+        let text = output.text();
+        if text.contains("Passed") {
+            result = Some(Outcome::Pass);
+        } else if text.contains("Failed") {
+            result = Some(Outcome::Fail(text.trim().replace('\n', " ")));
+        } else {
+            result = blargg_memory_result(gb);
+        }
+    }
+    return result.unwrap_or(Outcome::Fail(String::from("timed out")));
+}
+
+fn run_mooneye(gb: &mut Gameboy) -> Outcome {
+    if !run_to_breakpoint(gb, MOONEYE_SECONDS) {
+        return Outcome::Fail(String::from("timed out"));
+    }
+    let registers = [
+        gb.cpu.bc.hi(), gb.cpu.bc.lo(),
+        gb.cpu.de.hi(), gb.cpu.de.lo(),
+        gb.cpu.hl.hi(), gb.cpu.hl.lo(),
+    ];
+    if registers == FIBONACCI {
+        return Outcome::Pass;
+    }
+    return Outcome::Fail(format!("registers {:02X?}", registers));
+}
+
+// load_reference returns the RGB colour of every pixel of a reference image.
+fn load_reference(path: &Path) -> Result<Vec<[u8; 3]>, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mut decoder = png::Decoder::new(file);
+    // Expand palette images and bit depths below 8 to plain 8 bit pixels.
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let (info, mut reader) = decoder.read_info().map_err(|e| e.to_string())?;
+    if info.width != SCREEN_WIDTH as u32 || info.height != SCREEN_HEIGHT as u32 {
+        return Err(format!("reference is {}x{}", info.width, info.height));
+    }
+    let mut buffer = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buffer).map_err(|e| e.to_string())?;
+
+    let samples = info.color_type.samples();
+    let pixels = buffer.chunks(samples).map(|pixel| {
+        if samples < 3 {
+            // Grayscale, with or without alpha.
+            [pixel[0], pixel[0], pixel[0]]
+        } else {
+            [pixel[0], pixel[1], pixel[2]]
+        }
+    }).collect();
+    return Ok(pixels);
+}
+
+// to_5_bit undoes the conversion of a CGB colour channel to 8 bits, which
+// reference images and the emulator round differently.
+fn to_5_bit(value: u8) -> u8 {
+    return ((value as u32 * 31 + 127) / 255) as u8;
+}
+
+// dmg_shade returns which of the 4 DMG shades a grey is closest to, 0 being
+// white.
+fn dmg_shade(value: u8) -> u8 {
+    return 3 - ((value as u32 + 42) / 85) as u8;
+}
+
+// same_color compares a pixel drawn by the emulator with a reference pixel.
+// DMG screens are compared by shade, as the emulator draws them with its
+// grayscale palette rather than the reference's greys.
+fn same_color(gb: &Gameboy, x: usize, y: usize, reference: [u8; 3]) -> bool {
+    let pixel = gb.rendered_screen[x][y];
+    if gb.cgb_mode {
+        return to_5_bit(pixel.r) == to_5_bit(reference[0])
+            && to_5_bit(pixel.g) == to_5_bit(reference[1])
+            && to_5_bit(pixel.b) == to_5_bit(reference[2]);
+    }
+    let shade = (0..4).find(|&i| gb.get_palette_color(i).r == pixel.r);
+    return shade == Some(dmg_shade(reference[0]) as usize);
+}
+
+fn run_screenshot(gb: &mut Gameboy, reference: &Path) -> Outcome {
+    let reference = match load_reference(reference) {
+        Ok(reference) => reference,
+        Err(e) => return Outcome::Fail(format!("unable to load {}: {}", reference.display(), e)),
+    };
+    gb.current_palette = PALETTE_GRAYSCALE as usize;
+    if !run_to_breakpoint(gb, SCREENSHOT_SECONDS) {
+        return Outcome::Fail(String::from("timed out"));
+    }
+    // Let the frame being drawn at the breakpoint finish.
+    gb.update();
+
+    let mut wrong = 0;
+    for y in 0..SCREEN_HEIGHT as usize {
+        for x in 0..SCREEN_WIDTH as usize {
+            if !same_color(gb, x, y, reference[y * SCREEN_WIDTH as usize + x]) {
+                wrong += 1;
+            }
+        }
+    }
+    if wrong == 0 {
+        return Outcome::Pass;
+    }
+    return Outcome::Fail(format!("{} pixels differ", wrong));
+}
+
+fn run_entry(directory: &Path, entry: &Entry) -> Outcome {
+    let rom = directory.join(&entry.rom);
+    if !rom.exists() {
+        return Outcome::Missing;
+    }
+    let reference = entry.reference.as_ref().map(|reference| directory.join(reference));
+    let kind = entry.kind;
+
+    // A ROM hitting something the emulator doesn't support shouldn't stop
+    // the rest of the suite from running.
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut gb = match Gameboy::new(rom.to_str().unwrap()) {
+            Ok(gb) => gb,
+            Err(e) => return Outcome::Fail(e),
+        };
+        return match kind {
+            Kind::Blargg => run_blargg(&mut gb),
+            Kind::Mooneye => run_mooneye(&mut gb),
+            Kind::Screenshot => match &reference {
+                Some(reference) => run_screenshot(&mut gb, reference),
+                None => Outcome::Fail(String::from("no reference image")),
+            },
+        };
+    }));
+    return result.unwrap_or(Outcome::Fail(String::from("panicked")));
+}
+
+fn rom_directory() -> PathBuf {
+    return match env::var_os("GB_TEST_ROMS") {
+        Some(directory) => PathBuf::from(directory),
+        None => Path::new(env!("CARGO_MANIFEST_DIR")).join("test-roms"),
+    };
+}
+
+#[test]
+#[ignore = "needs the test ROMs, run with --ignored"]
+fn test_roms() {
+    let directory = rom_directory();
+    assert!(directory.is_dir(), "Test ROMs not found in {}, set GB_TEST_ROMS", directory.display());
+    let expected_path = Path::new(env!("CARGO_MANIFEST_DIR")).join(EXPECTED_RESULTS);
+    let entries = read_expected(&expected_path);
+
+    let mut results = Vec::new();
+    let mut regressions = Vec::new();
+    let mut ran = 0;
+    let mut passing = 0;
+    for entry in entries.iter() {
+        let (passed, status) = match run_entry(&directory, entry) {
+            Outcome::Pass => (true, String::from("pass")),
+            Outcome::Fail(reason) => (false, format!("fail ({})", reason)),
+            Outcome::Missing => {
+                // A ROM expected to pass has to be there, or a ROM set laid
+                // out differently would pass the suite without running it.
+                if entry.expected {
+                    regressions.push(entry.rom.clone());
+                }
+                println!("{:<60} missing", entry.rom);
+                results.push(entry.expected);
+                continue;
+            }
+        };
+        let note = if passed == entry.expected {
+            ""
+        } else if passed {
+            " [now passes]"
+        } else {
+            regressions.push(entry.rom.clone());
+            " [REGRESSION]"
+        };
+        println!("{:<60} {}{}", entry.rom, status, note);
+        ran += 1;
+        if passed {
+            passing += 1;
+        }
+        results.push(passed);
+    }
+
+    if env::var_os("GB_TEST_ROMS_BLESS").is_some() {
+        write_expected(&expected_path, &entries, &results);
+        return;
+    }
+    println!("{} test ROMs run, {} passed", ran, passing);
+    assert!(regressions.is_empty(), "Test ROMs no longer passing: {:?}", regressions);
+}